@@ -1,4 +1,4 @@
-use std::io;
+use std::{collections::BTreeMap, io, path::Path};
 #[cfg(windows)]
 use winres::WindowsResource;
 
@@ -8,6 +8,15 @@ fn main() -> io::Result<()> {
     // a rebuild instead of baking in a stale (or missing) value from the cache.
     println!("cargo:rerun-if-env-changed=VERSION");
 
+    // Native strings (tray, dialogs, splash) come from a CSV catalog compiled
+    // into OUT_DIR/l10n.rs; see src/l10n.rs.
+    generate_l10n()?;
+
+    // Updates signed by no key we know are refused, and the updater switches
+    // itself off rather than fail every check. Fine for a dev build, never for a
+    // release; see src/autoupdate/signing.rs.
+    check_update_keys()?;
+
     #[cfg(windows)]
     {
         // This embeds a Windows manifest into the Rust executable to prompt the user for administrator privileges.
@@ -21,3 +30,133 @@ fn main() -> io::Result<()> {
 
     Ok(())
 }
+
+const UPDATE_KEYS: &str = "src/autoupdate/update-keys.txt";
+
+fn check_update_keys() -> io::Result<()> {
+    println!("cargo:rerun-if-changed={UPDATE_KEYS}");
+    if std::env::var("PROFILE").as_deref() != Ok("release") {
+        return Ok(());
+    }
+    let keys = std::fs::read_to_string(UPDATE_KEYS)?;
+    let trusted = keys
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or("").trim())
+        .any(|line| !line.is_empty() && line.split_whitespace().nth(2) != Some("revoked"));
+    if trusted {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "{UPDATE_KEYS} lists no trusted key; a release built without one could never update"
+        )))
+    }
+}
+
+/// Language columns, in the order of `l10n::Lang`'s variants.
+const LANGS: [&str; 8] = ["en", "zh-CN", "zh-TW", "fa", "ar", "ru", "es", "uk"];
+
+/// The frontend's catalog, when the `gephgui` submodule is checked out. Merged
+/// first so native code can use any of its keys; ours wins where both define one.
+const FRONTEND_CATALOG: &str = "gephgui/src/lib/l10n.csv";
+const NATIVE_CATALOG: &str = "src/l10n.csv";
+
+fn generate_l10n() -> io::Result<()> {
+    println!("cargo:rerun-if-changed={FRONTEND_CATALOG}");
+    println!("cargo:rerun-if-changed={NATIVE_CATALOG}");
+
+    let mut catalog: BTreeMap<String, [String; 8]> = BTreeMap::new();
+    if let Ok(text) = std::fs::read_to_string(FRONTEND_CATALOG) {
+        merge_catalog(&mut catalog, FRONTEND_CATALOG, &text, false);
+    }
+    merge_catalog(
+        &mut catalog,
+        NATIVE_CATALOG,
+        &std::fs::read_to_string(NATIVE_CATALOG)?,
+        true,
+    );
+
+    let mut out = String::from("static CATALOG: &[(&str, [&str; 8])] = &[\n");
+    for (key, texts) in &catalog {
+        out.push_str(&format!("    ({key:?}, {texts:?}),\n"));
+    }
+    out.push_str("];\n");
+    let dest = Path::new(&std::env::var("OUT_DIR").unwrap()).join("l10n.rs");
+    std::fs::write(dest, out)
+}
+
+/// Add one CSV catalog to `catalog`. The header row names the key column first,
+/// then one column per language; columns for languages we don't ship are ignored,
+/// and empty cells leave whatever an earlier catalog had. `strict` is for our own
+/// file, whose mistakes should fail the build rather than show up as raw keys.
+fn merge_catalog(catalog: &mut BTreeMap<String, [String; 8]>, name: &str, text: &str, strict: bool) {
+    let mut rows = parse_csv(text.trim_start_matches('\u{feff}')).into_iter();
+    let Some(header) = rows.next() else {
+        return;
+    };
+    let columns: Vec<Option<usize>> = header
+        .iter()
+        .map(|code| lang_index(code.trim()))
+        .collect();
+    let mut seen = std::collections::HashSet::new();
+    for row in rows {
+        let Some(key) = row.first().map(|key| key.trim()).filter(|key| !key.is_empty()) else {
+            continue;
+        };
+        if strict && !seen.insert(key.to_string()) {
+            panic!("{name}: duplicate key {key:?}");
+        }
+        let entry = catalog.entry(key.to_string()).or_default();
+        for (cell, column) in row.iter().zip(&columns).skip(1) {
+            if let (Some(index), false) = (column, cell.is_empty()) {
+                entry[*index] = cell.clone();
+            }
+        }
+        if strict && entry[0].is_empty() {
+            panic!("{name}: key {key:?} has no English text");
+        }
+    }
+}
+
+/// Map a catalog column header to its index in `LANGS`. Accepts the usual
+/// spellings (`zh_CN`, `zh-Hans`, bare `zh`, …).
+fn lang_index(code: &str) -> Option<usize> {
+    let code = code.to_ascii_lowercase().replace('_', "-");
+    let canonical = match code.as_str() {
+        "zh" | "zh-cn" | "zh-hans" | "zh-sg" => "zh-CN",
+        "zh-tw" | "zh-hant" | "zh-hk" | "zh-mo" => "zh-TW",
+        other => other,
+    };
+    LANGS.iter().position(|lang| *lang == canonical)
+}
+
+/// Minimal RFC 4180 parsing: quoted fields may contain commas, newlines and
+/// doubled quotes.
+fn parse_csv(text: &str) -> Vec<Vec<String>> {
+    let mut rows = vec![];
+    let mut row = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            ('"', true) => quoted = false,
+            ('"', false) if field.is_empty() => quoted = true,
+            (',', false) => row.push(std::mem::take(&mut field)),
+            ('\r', false) => {}
+            ('\n', false) => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            (c, _) => field.push(c),
+        }
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    rows
+}
@@ -0,0 +1,272 @@
+//! Launch-at-login management, so the web UI can offer it as a setting.
+//!
+//! In every case the autostarted instance is launched with `--hidden`, which
+//! brings it up as just the tray icon (see main.rs).
+//!
+//!   * Linux: a freedesktop autostart entry, `~/.config/autostart/geph.desktop`.
+//!     Under Flatpak the entry must `flatpak run` the app, and it must land in
+//!     the *host's* `~/.config/autostart` rather than the sandbox-private
+//!     `$XDG_CONFIG_HOME`; the manifest grants
+//!     `--filesystem=xdg-config/autostart:create` for exactly this.
+//!   * Windows: the installer puts a `--hidden` shortcut in the all-users
+//!     Startup folder ({commonstartup} in setup.iss). Removing it needs admin, so
+//!     we toggle it the way Task Manager's "Startup apps" page does, via the
+//!     per-user `StartupApproved` registry flag. Installs without that shortcut
+//!     fall back to a per-user `Run` value.
+//!   * macOS: not supported yet.
+
+/// Whether launch-at-login can be managed on this platform.
+pub const SUPPORTED: bool = cfg!(any(target_os = "linux", target_os = "windows"));
+
+/// Whether Geph is currently set to launch at login.
+pub fn is_enabled() -> anyhow::Result<bool> {
+    imp::is_enabled()
+}
+
+/// Turn launch-at-login on or off.
+pub fn set_enabled(enabled: bool) -> anyhow::Result<()> {
+    imp::set_enabled(enabled)
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use std::path::PathBuf;
+
+    use anyhow::Context;
+
+    const DESKTOP_FILE: &str = "geph.desktop";
+
+    fn autostart_dir() -> anyhow::Result<PathBuf> {
+        if std::env::var_os("FLATPAK_ID").is_some() {
+            // $HOME is the real host home inside the sandbox; $XDG_CONFIG_HOME
+            // is not.
+            Ok(dirs::home_dir()
+                .context("no home dir")?
+                .join(".config")
+                .join("autostart"))
+        } else {
+            Ok(dirs::config_dir().context("no config dir")?.join("autostart"))
+        }
+    }
+
+    /// The `Exec=` command line that relaunches *this* installation hidden.
+    fn exec_line() -> anyhow::Result<String> {
+        if let Ok(app_id) = std::env::var("FLATPAK_ID") {
+            return Ok(format!("flatpak run {} --hidden", quote(&app_id)));
+        }
+        // An AppImage's current_exe() is inside a temporary FUSE mount; the
+        // runtime tells us where the image itself lives.
+        let exe = match std::env::var_os("APPIMAGE") {
+            Some(path) => PathBuf::from(path),
+            None => std::env::current_exe().context("cannot locate our own executable")?,
+        };
+        Ok(format!("{} --hidden", quote(&exe.to_string_lossy())))
+    }
+
+    /// Quote an argument per the Desktop Entry spec's `Exec` rules. A literal `%`
+    /// is written `%%`, since field codes are expanded even inside quotes. The
+    /// backslashes that quoting adds are then escaped again, as `Exec` is also a
+    /// string value whose `\\` escape is undone before the quoting rules apply.
+    fn quote(arg: &str) -> String {
+        let arg = arg.replace('%', "%%");
+        if !arg.contains(|c: char| c.is_whitespace() || "\"'\\`$;&|<>()*?#~=%".contains(c)) {
+            return arg;
+        }
+        let mut out = String::from("\"");
+        for c in arg.chars() {
+            if matches!(c, '"' | '`' | '$' | '\\') {
+                out.push('\\');
+            }
+            out.push(c);
+        }
+        out.push('"');
+        out.replace('\\', "\\\\")
+    }
+
+    pub fn is_enabled() -> anyhow::Result<bool> {
+        Ok(autostart_dir()?.join(DESKTOP_FILE).exists())
+    }
+
+    pub fn set_enabled(enabled: bool) -> anyhow::Result<()> {
+        let dir = autostart_dir()?;
+        let path = dir.join(DESKTOP_FILE);
+        if enabled {
+            std::fs::create_dir_all(&dir).with_context(|| format!("mkdir {}", dir.display()))?;
+            let entry = format!(
+                "[Desktop Entry]\n\
+                 Type=Application\n\
+                 Name=Geph\n\
+                 Exec={}\n\
+                 Icon={}\n\
+                 Terminal=false\n\
+                 X-GNOME-Autostart-enabled=true\n",
+                exec_line()?,
+                std::env::var("FLATPAK_ID").unwrap_or_else(|_| "geph".into()),
+            );
+            std::fs::write(&path, entry).with_context(|| format!("write {}", path.display()))?;
+        } else {
+            match std::fs::remove_file(&path) {
+                Ok(()) => {}
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => {
+                    return Err(err).with_context(|| format!("remove {}", path.display()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn exec_arguments_are_escaped() {
+            assert_eq!(quote("/usr/bin/geph"), "/usr/bin/geph");
+            assert_eq!(quote("/opt/my apps/geph"), r#""/opt/my apps/geph""#);
+            assert_eq!(quote("/opt/50%/geph"), r#""/opt/50%%/geph""#);
+            assert_eq!(
+                quote(r#"/home/a\b/"$x"/geph"#),
+                r#""/home/a\\\\b/\\"\\$x\\"/geph""#
+            );
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod imp {
+    use std::{ffi::OsStr, os::windows::ffi::OsStrExt, path::PathBuf};
+
+    use anyhow::Context;
+    use windows_sys::Win32::{
+        Foundation::{ERROR_FILE_NOT_FOUND, ERROR_SUCCESS},
+        System::Registry::{
+            HKEY_CURRENT_USER, REG_BINARY, REG_SZ, RRF_RT_REG_BINARY, RRF_RT_REG_SZ,
+            RegDeleteKeyValueW, RegGetValueW, RegSetKeyValueW,
+        },
+    };
+
+    /// The shortcut setup.iss drops into {commonstartup}.
+    const STARTUP_SHORTCUT: &str = "Geph.lnk";
+    const APPROVED_KEY: &str =
+        r"Software\Microsoft\Windows\CurrentVersion\Explorer\StartupApproved\StartupFolder";
+    const RUN_KEY: &str = r"Software\Microsoft\Windows\CurrentVersion\Run";
+    const RUN_VALUE: &str = "Geph";
+
+    fn wide(s: impl AsRef<OsStr>) -> Vec<u16> {
+        s.as_ref().encode_wide().chain(std::iter::once(0)).collect()
+    }
+
+    fn common_shortcut() -> Option<PathBuf> {
+        let path = PathBuf::from(std::env::var_os("ProgramData")?)
+            .join(r"Microsoft\Windows\Start Menu\Programs\StartUp")
+            .join(STARTUP_SHORTCUT);
+        path.exists().then_some(path)
+    }
+
+    /// The `StartupApproved` blob: 12 bytes, the first of which is even when the
+    /// entry is enabled (0x02) and odd when disabled (0x03). No value at all
+    /// means "never toggled", i.e. enabled.
+    fn shortcut_approved() -> bool {
+        let (key, value) = (wide(APPROVED_KEY), wide(STARTUP_SHORTCUT));
+        let mut data = [0u8; 12];
+        let mut len = data.len() as u32;
+        let status = unsafe {
+            RegGetValueW(
+                HKEY_CURRENT_USER,
+                key.as_ptr(),
+                value.as_ptr(),
+                RRF_RT_REG_BINARY,
+                std::ptr::null_mut(),
+                data.as_mut_ptr().cast(),
+                &mut len,
+            )
+        };
+        status != ERROR_SUCCESS || data[0] & 1 == 0
+    }
+
+    fn set_shortcut_approved(enabled: bool) -> anyhow::Result<()> {
+        let (key, value) = (wide(APPROVED_KEY), wide(STARTUP_SHORTCUT));
+        let mut data = [0u8; 12];
+        data[0] = if enabled { 0x02 } else { 0x03 };
+        let status = unsafe {
+            RegSetKeyValueW(
+                HKEY_CURRENT_USER,
+                key.as_ptr(),
+                value.as_ptr(),
+                REG_BINARY,
+                data.as_ptr().cast(),
+                data.len() as u32,
+            )
+        };
+        anyhow::ensure!(status == ERROR_SUCCESS, "RegSetKeyValueW failed ({status})");
+        Ok(())
+    }
+
+    fn run_value_present() -> bool {
+        let (key, value) = (wide(RUN_KEY), wide(RUN_VALUE));
+        let status = unsafe {
+            RegGetValueW(
+                HKEY_CURRENT_USER,
+                key.as_ptr(),
+                value.as_ptr(),
+                RRF_RT_REG_SZ,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            )
+        };
+        status == ERROR_SUCCESS
+    }
+
+    fn set_run_value(enabled: bool) -> anyhow::Result<()> {
+        let (key, value) = (wide(RUN_KEY), wide(RUN_VALUE));
+        let status = if enabled {
+            let exe = std::env::current_exe().context("cannot locate our own executable")?;
+            let command = wide(format!("\"{}\" --hidden", exe.display()));
+            unsafe {
+                RegSetKeyValueW(
+                    HKEY_CURRENT_USER,
+                    key.as_ptr(),
+                    value.as_ptr(),
+                    REG_SZ,
+                    command.as_ptr().cast(),
+                    (command.len() * 2) as u32,
+                )
+            }
+        } else {
+            match unsafe { RegDeleteKeyValueW(HKEY_CURRENT_USER, key.as_ptr(), value.as_ptr()) } {
+                ERROR_FILE_NOT_FOUND => ERROR_SUCCESS,
+                status => status,
+            }
+        };
+        anyhow::ensure!(status == ERROR_SUCCESS, "updating the Run key failed ({status})");
+        Ok(())
+    }
+
+    pub fn is_enabled() -> anyhow::Result<bool> {
+        let shortcut = common_shortcut().is_some() && shortcut_approved();
+        Ok(shortcut || run_value_present())
+    }
+
+    pub fn set_enabled(enabled: bool) -> anyhow::Result<()> {
+        if common_shortcut().is_some() {
+            set_shortcut_approved(enabled)?;
+            // Never leave both mechanisms on, or we'd launch twice.
+            set_run_value(false)
+        } else {
+            set_run_value(enabled)
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+mod imp {
+    pub fn is_enabled() -> anyhow::Result<bool> {
+        Ok(false)
+    }
+
+    pub fn set_enabled(_enabled: bool) -> anyhow::Result<()> {
+        anyhow::bail!("launch at login is not supported on this platform")
+    }
+}
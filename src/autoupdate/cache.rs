@@ -0,0 +1,331 @@
+//! Housekeeping for the download cache.
+//!
+//! Every downloaded version gets its own `geph5-dl/<sha256>/` slot, and nothing
+//! used to remove them: a year of releases left a year of installers behind. Now
+//! the cache holds the pending update (the one `update-metadata.json` points at)
+//! and, if the user wants a rollback path, the installer of the version this one
+//! replaced. That takes two records: once an update turns out to have been
+//! installed, its metadata moves to `update-installed.json`, and whatever was
+//! there (the version it replaced) moves on to `update-previous.json`. Rolling
+//! back needs the latter; the former is kept so it can become the latter at the
+//! next update. Everything else is pruned at startup and whenever a new update
+//! is cached.
+//!
+//! The exception is a slot holding nothing but a `.part` file touched within
+//! `PART_MAX_AGE`: an interrupted download that the next check resumes
+//! (download.rs), which would otherwise start over after every restart. Older
+//! partial downloads are as stale as any other.
+
+use std::{
+    collections::BTreeSet,
+    fs,
+    io::ErrorKind,
+    path::Path,
+    sync::atomic::Ordering,
+    time::{Duration, SystemTime},
+};
+
+use semver::Version;
+use serde::Serialize;
+
+use super::{
+    CHECKING, INSTALLED_FILE, PREVIOUS_FILE, UpdateMetadata, UpdateStatus, Updater, deferred,
+    status,
+};
+use crate::prefs;
+
+/// How long an interrupted download is kept for resuming.
+const PART_MAX_AGE: Duration = Duration::from_secs(2 * 24 * 3600);
+
+#[derive(Clone, Debug, Serialize)]
+pub struct UpdateCacheInfo {
+    /// Bytes used by downloaded updates, partial ones included.
+    pub bytes: u64,
+    /// Whether the installer of the version before this one is kept for rollback.
+    pub keep_previous: bool,
+    /// The version of the kept installer, if there is one.
+    pub previous_version: Option<String>,
+}
+
+/// Startup tidying: retire the pending update if it has since been installed,
+/// then prune.
+pub fn housekeep() -> anyhow::Result<()> {
+    let updater = Updater::system()?;
+    let keep_previous = prefs::get().keep_previous_installer;
+    retire_installed(&updater, keep_previous)?;
+    prune_keeping(&updater, keep_previous)
+}
+
+/// If the pending update is the version now running, it's been installed: with
+/// `keep_previous`, it becomes the installed record and the one it replaced the
+/// previous; otherwise it's just forgotten.
+fn retire_installed<S>(updater: &Updater<S>, keep_previous: bool) -> anyhow::Result<()> {
+    let Some(metadata) = updater.load_metadata()? else {
+        return Ok(());
+    };
+    if !Version::parse(&metadata.version)?
+        .cmp_precedence(&updater.current)
+        .is_eq()
+    {
+        return Ok(());
+    }
+    if keep_previous {
+        tracing::debug!(version = %metadata.version, "keeping the installed update for rollback");
+        let installed = updater.root.join(INSTALLED_FILE);
+        if installed.exists() {
+            fs::rename(&installed, updater.root.join(PREVIOUS_FILE))?;
+        }
+        fs::rename(updater.metadata_path(), installed)?;
+    } else {
+        updater.clear_metadata()?;
+    }
+    Ok(())
+}
+
+/// Delete every download except the pending update, the installers kept for
+/// rollback, and recent partial downloads.
+pub fn prune<S>(updater: &Updater<S>) -> anyhow::Result<()> {
+    prune_keeping(updater, prefs::get().keep_previous_installer)
+}
+
+fn prune_keeping<S>(updater: &Updater<S>, keep_previous: bool) -> anyhow::Result<()> {
+    let root = &updater.root;
+    let mut keep = BTreeSet::new();
+    if let Some(metadata) = updater.load_metadata()? {
+        keep.insert(metadata.sha256);
+    }
+    for file in [INSTALLED_FILE, PREVIOUS_FILE] {
+        if !keep_previous {
+            remove_file(&root.join(file))?;
+        } else if let Some(kept) = load_record(root, file) {
+            keep.insert(kept.sha256);
+        }
+    }
+
+    for entry in fs::read_dir(root)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let Some(name) = name.to_str().filter(|name| is_slot(name)) else {
+            continue;
+        };
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        let slot = entry.path();
+        if keep.contains(name) {
+            // Complete and verified; any leftover `.part` is dead weight.
+            for file in fs::read_dir(&slot)? {
+                let file = file?.path();
+                if file.extension().is_some_and(|ext| ext == "part") {
+                    remove_file(&file)?;
+                }
+            }
+        } else if !resumable(&slot) {
+            tracing::debug!(slot = %slot.display(), "pruning stale update download");
+            fs::remove_dir_all(&slot)?;
+        }
+    }
+    Ok(())
+}
+
+/// What the cache holds, for the webview.
+pub fn info() -> anyhow::Result<UpdateCacheInfo> {
+    let root = Updater::system()?.root;
+    let keep_previous = prefs::get().keep_previous_installer;
+    Ok(UpdateCacheInfo {
+        bytes: dir_size(&root)?,
+        keep_previous,
+        previous_version: load_record(&root, PREVIOUS_FILE)
+            .filter(|_| keep_previous)
+            .map(|previous| previous.version),
+    })
+}
+
+/// Keep (or stop keeping) the previous version's installer for rollback.
+pub fn set_keep_previous(keep: bool) -> anyhow::Result<()> {
+    prefs::update(|p| p.keep_previous_installer = keep)?;
+    prune(&Updater::system()?)
+}
+
+/// Delete every downloaded update, keeping the user's decisions and the check
+/// schedule. Refused while a check may be writing into the cache.
+pub fn clear_downloads() -> anyhow::Result<()> {
+    if CHECKING.load(Ordering::SeqCst) {
+        anyhow::bail!("an update is being checked for or downloaded; try again later");
+    }
+    let updater = Updater::system()?;
+    updater.clear_metadata()?;
+    deferred::set(None);
+    let root = &updater.root;
+    remove_file(&root.join(INSTALLED_FILE))?;
+    remove_file(&root.join(PREVIOUS_FILE))?;
+    for entry in fs::read_dir(root)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() && entry.file_name().to_str().is_some_and(is_slot) {
+            fs::remove_dir_all(entry.path())?;
+        }
+    }
+    status::set(UpdateStatus::Idle);
+    Ok(())
+}
+
+fn load_record(root: &Path, file: &str) -> Option<UpdateMetadata> {
+    let bytes = fs::read(root.join(file)).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+/// Slots are named after the download's SHA-256; leave anything else alone.
+fn is_slot(name: &str) -> bool {
+    name.len() == 64 && name.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Whether `slot` holds only an interrupted download recent enough to resume.
+fn resumable(slot: &Path) -> bool {
+    let Ok(files) = fs::read_dir(slot) else {
+        return false;
+    };
+    let mut found = false;
+    for file in files.flatten() {
+        let path = file.path();
+        if path.extension().is_none_or(|ext| ext != "part") {
+            return false;
+        }
+        let fresh = file
+            .metadata()
+            .and_then(|meta| meta.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .is_some_and(|age| age < PART_MAX_AGE);
+        found |= fresh;
+    }
+    found
+}
+
+fn dir_size(dir: &Path) -> anyhow::Result<u64> {
+    let mut total = 0;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let meta = entry.metadata()?;
+        total += if meta.is_dir() {
+            dir_size(&entry.path())?
+        } else {
+            meta.len()
+        };
+    }
+    Ok(total)
+}
+
+fn remove_file(path: &Path) -> anyhow::Result<()> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_fresh_partial_downloads_are_resumable() {
+        let slot = std::env::temp_dir().join(format!("geph-cache-test-{}", std::process::id()));
+        fs::create_dir_all(&slot).unwrap();
+        assert!(!resumable(&slot));
+
+        fs::write(slot.join("geph.deb.part"), b"half").unwrap();
+        assert!(resumable(&slot));
+
+        let old = SystemTime::now() - PART_MAX_AGE * 2;
+        fs::File::options()
+            .write(true)
+            .open(slot.join("geph.deb.part"))
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+        assert!(!resumable(&slot));
+
+        fs::write(slot.join("geph.deb"), b"whole").unwrap();
+        assert!(!resumable(&slot));
+        fs::remove_dir_all(&slot).unwrap();
+    }
+
+    /// An updater on the first of `versions` over a scratch cache, and metadata
+    /// for each version's download.
+    fn scratch(versions: &[&str]) -> (Updater<()>, Vec<UpdateMetadata>) {
+        let root = std::env::temp_dir().join(format!("geph-rollback-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let records = versions
+            .iter()
+            .enumerate()
+            .map(|(i, version)| {
+                let sha256 = format!("{i:x}").repeat(64);
+                let slot = root.join(&sha256);
+                UpdateMetadata {
+                    version: version.to_string(),
+                    download_path: slot.join("geph.bin"),
+                    sha256,
+                    filename: "geph.bin".into(),
+                    signature: String::new(),
+                    notes: None,
+                    track: String::new(),
+                }
+            })
+            .collect();
+        let updater = Updater {
+            source: (),
+            keys: super::super::KeyRing::parse("").unwrap(),
+            root,
+            current: Version::parse(versions[0]).unwrap(),
+            channel: crate::prefs::UpdateChannel::Stable,
+        };
+        (updater, records)
+    }
+
+    /// Download `record` and make it the pending update.
+    fn cache(updater: &Updater<()>, record: &UpdateMetadata) {
+        fs::create_dir_all(record.download_path.parent().unwrap()).unwrap();
+        fs::write(&record.download_path, &record.version).unwrap();
+        updater.write_metadata(record).unwrap();
+    }
+
+    #[test]
+    fn the_replaced_versions_installer_is_kept() {
+        let (mut updater, records) = scratch(&["5.1.0", "5.2.0", "5.3.0"]);
+        let kept = || {
+            records
+                .iter()
+                .filter(|record| record.download_path.exists())
+                .map(|record| record.version.as_str())
+                .collect::<Vec<_>>()
+        };
+
+        // 5.1.0 (installed by hand) updates to 5.2.0: nothing to roll back to yet.
+        cache(&updater, &records[1]);
+        updater.current = Version::parse("5.2.0").unwrap();
+        retire_installed(&updater, true).unwrap();
+        prune_keeping(&updater, true).unwrap();
+        assert_eq!(kept(), ["5.2.0"]);
+        assert!(load_record(&updater.root, PREVIOUS_FILE).is_none());
+
+        // 5.2.0 updates to 5.3.0: 5.2.0's installer is the rollback.
+        cache(&updater, &records[2]);
+        updater.current = Version::parse("5.3.0").unwrap();
+        retire_installed(&updater, true).unwrap();
+        prune_keeping(&updater, true).unwrap();
+        assert_eq!(kept(), ["5.2.0", "5.3.0"]);
+        let previous = load_record(&updater.root, PREVIOUS_FILE).unwrap();
+        assert_eq!(previous.version, "5.2.0");
+
+        // Turning rollback off lets both go.
+        prune_keeping(&updater, false).unwrap();
+        assert!(kept().is_empty());
+        fs::remove_dir_all(&updater.root).unwrap();
+    }
+
+    #[test]
+    fn slots_are_sha256_names() {
+        assert!(is_slot(&"ab".repeat(32)));
+        assert!(!is_slot("update-metadata.json"));
+        assert!(!is_slot(&"zz".repeat(32)));
+    }
+}
@@ -0,0 +1,139 @@
+//! Update channels, and which manifest entry is worth moving to.
+//!
+//! The manifest has one entry per track, `<platform>-<channel>`:
+//! `linux-stable`, `windows-beta`, `linux-appimage-nightly`, …; the channel comes
+//! from prefs.rs. Versions
+//! are semver, and a build's pre-release tag says which channel it came from:
+//! none for stable, `beta.N` or `rc.N` for beta, anything else (`nightly.<date>`,
+//! `alpha`, `dev`) for nightly. Precedence follows semver, so `5.2.0-beta.2`
+//! sorts after `5.2.0-beta.1` and before `5.2.0`, and build metadata (`+abc`) is
+//! ignored.
+//!
+//! We never downgrade within a channel. But someone who dogfooded `5.2.0-beta.3`
+//! and then switched back to stable would otherwise sit on that beta until stable
+//! passed it, still getting none of the fixes stable gets in the meantime. So a
+//! build from a less stable channel than the selected one is offered the
+//! channel's release even if it's older, as an explicit downgrade.
+
+use std::cmp::Ordering;
+
+use semver::Version;
+
+use crate::prefs::UpdateChannel;
+
+#[cfg(target_os = "linux")]
+const OS: &str = "linux";
+
+#[cfg(target_os = "windows")]
+const OS: &str = "windows";
+
+#[cfg(target_os = "macos")]
+const OS: &str = "macos";
+
+/// What installing an update would do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Offer {
+    Upgrade,
+    /// Back to the selected channel's (older) release from a less stable build.
+    Downgrade,
+}
+
+/// The manifest key for `channel` on this platform.
+pub fn track(channel: UpdateChannel) -> String {
+    format!("{}-{}", platform(), channel.name())
+}
+
+/// The OS, and on Linux the package format too: a `.deb` can't replace an
+/// AppImage.
+fn platform() -> &'static str {
+    #[cfg(target_os = "linux")]
+    if let super::linux::Install::AppImage(_) = super::linux::Install::current() {
+        return "linux-appimage";
+    }
+    OS
+}
+
+/// The channel a version was released on, from its pre-release tag.
+pub fn channel_of(version: &Version) -> UpdateChannel {
+    let tag = version.pre.as_str();
+    if tag.is_empty() {
+        UpdateChannel::Stable
+    } else if tag.starts_with("beta") || tag.starts_with("rc") {
+        UpdateChannel::Beta
+    } else {
+        UpdateChannel::Nightly
+    }
+}
+
+/// Whether `candidate`, from `channel`'s track, should replace `current`.
+pub fn offer(candidate: &Version, current: &Version, channel: UpdateChannel) -> Option<Offer> {
+    match candidate.cmp_precedence(current) {
+        Ordering::Greater => Some(Offer::Upgrade),
+        Ordering::Equal => None,
+        Ordering::Less if channel_of(current) > channel => Some(Offer::Downgrade),
+        Ordering::Less => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(version: &str) -> Version {
+        Version::parse(version).unwrap()
+    }
+
+    #[test]
+    fn prereleases_sort_before_their_release() {
+        let stable = UpdateChannel::Stable;
+        let beta = UpdateChannel::Beta;
+        assert_eq!(
+            offer(&v("5.2.0"), &v("5.2.0-beta.3"), beta),
+            Some(Offer::Upgrade)
+        );
+        assert_eq!(
+            offer(&v("5.2.0-beta.10"), &v("5.2.0-beta.9"), beta),
+            Some(Offer::Upgrade)
+        );
+        assert_eq!(offer(&v("5.2.0-beta.1"), &v("5.2.0"), beta), None);
+        // Build metadata says nothing about which is newer.
+        assert_eq!(offer(&v("5.1.0+b2"), &v("5.1.0+b1"), stable), None);
+    }
+
+    #[test]
+    fn channels_come_from_the_prerelease_tag() {
+        assert_eq!(channel_of(&v("5.1.0")), UpdateChannel::Stable);
+        assert_eq!(channel_of(&v("5.1.0+build.7")), UpdateChannel::Stable);
+        assert_eq!(channel_of(&v("5.2.0-rc.1")), UpdateChannel::Beta);
+        assert_eq!(
+            channel_of(&v("5.2.0-nightly.20261018")),
+            UpdateChannel::Nightly
+        );
+    }
+
+    #[test]
+    fn leaving_a_test_channel_downgrades() {
+        assert_eq!(
+            offer(&v("5.1.4"), &v("5.2.0-beta.3"), UpdateChannel::Stable),
+            Some(Offer::Downgrade)
+        );
+        assert_eq!(
+            offer(
+                &v("5.2.0-beta.3"),
+                &v("5.3.0-nightly.1"),
+                UpdateChannel::Beta
+            ),
+            Some(Offer::Downgrade)
+        );
+        // Still on the build's own channel, or a less stable one: never.
+        assert_eq!(offer(&v("5.1.4"), &v("5.1.5"), UpdateChannel::Stable), None);
+        assert_eq!(
+            offer(
+                &v("5.2.0-beta.2"),
+                &v("5.2.0-beta.3"),
+                UpdateChannel::Nightly
+            ),
+            None
+        );
+    }
+}
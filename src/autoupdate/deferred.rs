@@ -0,0 +1,126 @@
+//! Installing an accepted update later, when it won't cut anyone off.
+//!
+//! Installing stops the tunnel and quits (see `install`), which drops whatever
+//! the user was doing through it. So an update accepted while connected can
+//! instead wait for the first safe moment: the next time the tunnel goes down
+//! ("when disconnected"), or the user quitting Geph ("on quit"). Quitting counts
+//! for both, since it disconnects anyway.
+//!
+//! The tray's once-a-second state poll reports the tunnel through
+//! `observe_state`, and the quitting paths in tray.rs call `install_on_quit`.
+//! Three moments don't count. A poll the manager didn't answer (it timed out,
+//! or the manager is restarting) says nothing about the tunnel, which may well
+//! still be up. "Quit, keep connected" asked for the tunnel to stay
+//! up, which installing can't do. A pause (pause.rs) is only a gap: the user
+//! expects to be reconnected when it ends, and quitting for the installer would
+//! cancel that.
+//!
+//! A deferral is kept in memory only. If the GUI goes away without installing
+//! (killed, or quit keeping the tunnel), the update is offered again at the next
+//! start. It is also for one version: if a newer one is downloaded meanwhile,
+//! that one is offered afresh rather than installed sight unseen.
+
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::pause;
+
+/// When a deferred update gets installed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InstallWhen {
+    /// The next time the tunnel is down.
+    Disconnected,
+    /// When the user quits Geph.
+    Quit,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Deferral {
+    pub version: String,
+    pub when: InstallWhen,
+}
+
+static DEFERRAL: Mutex<Option<Deferral>> = Mutex::new(None);
+
+/// The update waiting to be installed, if any.
+pub fn get() -> Option<Deferral> {
+    DEFERRAL.lock().unwrap().clone()
+}
+
+pub(super) fn set(deferral: Option<Deferral>) {
+    *DEFERRAL.lock().unwrap() = deferral;
+}
+
+/// Take the deferral if `due` says its moment has come, so it runs only once.
+fn take_if(due: impl FnOnce(&Deferral) -> bool) -> Option<Deferral> {
+    let mut deferral = DEFERRAL.lock().unwrap();
+    if deferral.as_ref().is_some_and(due) {
+        deferral.take()
+    } else {
+        None
+    }
+}
+
+/// Whether a polled tunnel state is the disconnect an update waits for: the
+/// manager answered that the tunnel is down, and not because of a pause.
+fn disconnected(state: Option<bool>, paused: bool) -> bool {
+    state == Some(false) && !paused
+}
+
+/// Called with each polled tunnel state (`None` if the manager didn't answer):
+/// install an update waiting for a disconnect once the tunnel is down.
+pub fn observe_state(state: Option<bool>) {
+    if !disconnected(state, pause::remaining().is_some()) {
+        return;
+    }
+    let Some(deferral) = take_if(|d| d.when == InstallWhen::Disconnected) else {
+        return;
+    };
+    tracing::info!(version = %deferral.version, "tunnel is down; installing the deferred update");
+    geph5_rt::spawn(async move {
+        if let Err(err) = super::install_deferred(&deferral).await {
+            super::deferred_install_failed(err);
+        }
+    })
+    .detach();
+}
+
+/// Install a deferred update, of either kind, as the GUI quits. Only returns if
+/// there was none or installing failed, leaving the quitting to the caller.
+pub async fn install_on_quit() {
+    let Some(deferral) = take_if(|_| true) else {
+        return;
+    };
+    tracing::info!(version = %deferral.version, "quitting; installing the deferred update");
+    if let Err(err) = super::install_deferred(&deferral).await {
+        tracing::warn!(err = debug(err), "deferred update installation failed");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_deferral_is_taken_once() {
+        set(Some(Deferral {
+            version: "5.2.0".into(),
+            when: InstallWhen::Quit,
+        }));
+        assert_eq!(take_if(|d| d.when == InstallWhen::Disconnected), None);
+        assert!(get().is_some());
+        assert_eq!(take_if(|_| true).unwrap().version, "5.2.0");
+        assert_eq!(take_if(|_| true), None);
+    }
+
+    #[test]
+    fn only_an_answered_disconnect_counts() {
+        assert!(disconnected(Some(false), false));
+        assert!(!disconnected(Some(true), false));
+        // The manager is unreachable: the tunnel may still be up.
+        assert!(!disconnected(None, false));
+        assert!(!disconnected(Some(false), true));
+    }
+}
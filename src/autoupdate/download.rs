@@ -0,0 +1,260 @@
+//! Streaming, resumable update downloads.
+//!
+//! Installers run to a hundred megabytes or so, on connections that are often
+//! slow and flaky (the update comes over the same censored networks Geph is for).
+//! So rather than buffering the whole body and writing it in place, we stream it
+//! into `<filename>.part` next to its final slot in `geph5-dl/<sha256>/`, hashing
+//! as we go. An interrupted download leaves the `.part` behind, and the next
+//! attempt (right away, or at the next update check) re-hashes what's there and
+//! asks for the rest with an HTTP `Range` request. Only a complete file whose hash
+//! matches is renamed into place, so anything at the final path is whole.
+//!
+//! Progress is kept for `update_download_progress` and pushed to the webview as
+//! `geph_update_progress` events (`null` once the download ends either way), and
+//! shows up as a percentage in the update status (status.rs).
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use reqwest::{StatusCode, header};
+use serde::Serialize;
+
+use super::UpdateStatus;
+
+/// Space to leave free on top of the download itself.
+const SPACE_MARGIN: u64 = 64 * 1024 * 1024;
+/// Tries per download; each retry resumes where the last one stopped.
+const ATTEMPTS: u32 = 3;
+/// How often to report progress while bytes are flowing.
+const PROGRESS_EVERY: Duration = Duration::from_millis(500);
+
+#[derive(Clone, Debug, Serialize)]
+pub struct DownloadProgress {
+    pub version: String,
+    pub downloaded: u64,
+    /// `None` if the server didn't say how big the file is.
+    pub total: Option<u64>,
+}
+
+static PROGRESS: Mutex<Option<DownloadProgress>> = Mutex::new(None);
+
+/// The download in progress, if any.
+pub fn progress() -> Option<DownloadProgress> {
+    PROGRESS.lock().unwrap().clone()
+}
+
+fn set_progress(progress: Option<DownloadProgress>) {
+    *PROGRESS.lock().unwrap() = progress.clone();
+    if let Some(progress) = &progress {
+        super::status::set(UpdateStatus::Downloading {
+            version: progress.version.clone(),
+            percent: progress
+                .total
+                .filter(|total| *total > 0)
+                .map(|total| (progress.downloaded * 100 / total).min(100) as u8),
+        });
+    }
+    crate::rpc::push_event("geph_update_progress", progress);
+}
+
+/// Download `url` to `dest`, which must hash to `sha256`, resuming any earlier
+/// partial download of it.
+pub async fn fetch(
+    client: &reqwest::Client,
+    url: &str,
+    dest: &Path,
+    sha256: &str,
+    version: &str,
+) -> anyhow::Result<()> {
+    let part = part_path(dest);
+    let result = fetch_with_retries(client, url, &part, version).await;
+    set_progress(None);
+    if result? != sha256 {
+        // Resuming can't fix a wrong file; start from scratch next time.
+        let _ = fs::remove_file(&part);
+        anyhow::bail!("Downloaded file hash mismatch");
+    }
+    fs::rename(&part, dest)?;
+    Ok(())
+}
+
+async fn fetch_with_retries(
+    client: &reqwest::Client,
+    url: &str,
+    part: &Path,
+    version: &str,
+) -> anyhow::Result<String> {
+    let mut attempt = 1;
+    loop {
+        match fetch_once(client, url, part, version).await {
+            Ok(hash) => return Ok(hash),
+            Err(err) if attempt < ATTEMPTS => {
+                tracing::debug!(attempt, err = debug(err), "update download interrupted");
+                tokio::time::sleep(Duration::from_secs(2 * attempt as u64)).await;
+                attempt += 1;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// One request's worth of downloading into `part`. Returns the hex SHA-256 of
+/// the whole file once the server has sent all of it.
+async fn fetch_once(
+    client: &reqwest::Client,
+    url: &str,
+    part: &Path,
+    version: &str,
+) -> anyhow::Result<String> {
+    let (mut hasher, mut have) = {
+        let part = part.to_path_buf();
+        geph5_rt::spawn_blocking(move || match hash_file(&part) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok((hmac_sha256::Hash::new(), 0)),
+            result => result,
+        })
+        .await?
+    };
+
+    let mut request = client.get(url);
+    if have > 0 {
+        tracing::debug!(have, "resuming update download");
+        request = request.header(header::RANGE, format!("bytes={have}-"));
+    }
+    let mut resp = request.send().await?;
+    let total = match resp.status() {
+        StatusCode::PARTIAL_CONTENT if have > 0 => match content_range(&resp) {
+            Some((start, total)) if start == have => total,
+            _ => {
+                let _ = fs::remove_file(part);
+                anyhow::bail!("server resumed from the wrong offset");
+            }
+        },
+        // We already have at least the whole file; the hash decides whether it's
+        // the right one.
+        StatusCode::RANGE_NOT_SATISFIABLE if have > 0 => {
+            return Ok(hex::encode(hasher.finalize()));
+        }
+        status if status.is_success() => {
+            // A server that ignores `Range` sends everything again.
+            if have > 0 {
+                hasher = hmac_sha256::Hash::new();
+                have = 0;
+            }
+            resp.content_length()
+        }
+        status => anyhow::bail!("update download failed: HTTP {status}"),
+    };
+
+    if let Some(total) = total {
+        let needed = total.saturating_sub(have);
+        match available_space(part.parent().unwrap_or(Path::new("."))) {
+            Ok(free) if free < needed + SPACE_MARGIN => anyhow::bail!(
+                "not enough disk space for the update: {needed} bytes needed, {free} available"
+            ),
+            Ok(_) => {}
+            Err(err) => tracing::debug!(err = debug(err), "could not check free disk space"),
+        }
+    }
+
+    let mut file = if have > 0 {
+        OpenOptions::new().append(true).open(part)?
+    } else {
+        File::create(part)?
+    };
+    let mut progress = DownloadProgress {
+        version: version.to_string(),
+        downloaded: have,
+        total,
+    };
+    set_progress(Some(progress.clone()));
+    let mut reported = Instant::now();
+    while let Some(chunk) = resp.chunk().await? {
+        file.write_all(&chunk)?;
+        hasher.update(&chunk);
+        progress.downloaded += chunk.len() as u64;
+        if reported.elapsed() >= PROGRESS_EVERY {
+            reported = Instant::now();
+            set_progress(Some(progress.clone()));
+        }
+    }
+    file.sync_all()?;
+    if let Some(total) = total.filter(|total| progress.downloaded < *total) {
+        anyhow::bail!(
+            "update download ended early ({} of {total} bytes)",
+            progress.downloaded
+        );
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Where `dest` is downloaded to until it's complete and verified.
+fn part_path(dest: &Path) -> PathBuf {
+    let mut name = dest.file_name().unwrap_or_default().to_os_string();
+    name.push(".part");
+    dest.with_file_name(name)
+}
+
+/// `(start, total)` from a `Content-Range: bytes <start>-<end>/<total>` header.
+fn content_range(resp: &reqwest::Response) -> Option<(u64, Option<u64>)> {
+    let value = resp.headers().get(header::CONTENT_RANGE)?.to_str().ok()?;
+    let (range, total) = value.strip_prefix("bytes ")?.split_once('/')?;
+    let start = range.split_once('-')?.0.parse().ok()?;
+    Some((start, total.parse().ok()))
+}
+
+/// Hash a file without reading it into memory at once. Returns the hasher (to
+/// continue from) and the number of bytes hashed.
+pub fn hash_file(path: &Path) -> io::Result<(hmac_sha256::Hash, u64)> {
+    let mut file = File::open(path)?;
+    let mut hasher = hmac_sha256::Hash::new();
+    let mut buf = vec![0; 64 * 1024];
+    let mut len = 0;
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            return Ok((hasher, len));
+        }
+        hasher.update(&buf[..n]);
+        len += n as u64;
+    }
+}
+
+/// Bytes available to us on the filesystem holding `dir`.
+#[cfg(unix)]
+fn available_space(dir: &Path) -> io::Result<u64> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let path = CString::new(dir.as_os_str().as_bytes())?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+/// Bytes available to us on the volume holding `dir`.
+#[cfg(windows)]
+fn available_space(dir: &Path) -> io::Result<u64> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+
+    let wide: Vec<u16> = dir.as_os_str().encode_wide().chain([0]).collect();
+    let mut free = 0u64;
+    if unsafe {
+        GetDiskFreeSpaceExW(
+            wide.as_ptr(),
+            &mut free,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        )
+    } == 0
+    {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(free)
+}
@@ -0,0 +1,204 @@
+//! Applying updates on Linux.
+//!
+//! Windows and macOS hand the download to an installer and quit. Linux has no
+//! such thing, and what an update even is depends on how we got here:
+//!
+//!   * from our `.deb` (`dpkg -S` owns our executable): the new `.deb` goes
+//!     through apt, or dpkg where there's no apt, as root via the same elevation
+//!     the bootstrap uses (elevate.rs). The cached file sits in the user's cache
+//!     dir, where anything running as the user could swap it after we checked
+//!     it, so the root side copies it into a fresh `mktemp` dir and checks the
+//!     hash there before installing that copy.
+//!   * as an AppImage (the runtime sets `$APPIMAGE` to the image's path): the
+//!     track is `linux-appimage-<channel>`, and the new image is copied next to
+//!     the old one, checked, and renamed over it, so the path holds either the
+//!     old image or the whole new one. The running copy is mounted from the old
+//!     inode and doesn't notice.
+//!   * anything else (a dev build, a tarball, another distro's package): we
+//!     don't know how to replace it, so updates aren't offered at all.
+//!
+//! Either way we then quit and start the new version once we're gone.
+
+use std::{
+    ffi::{CString, OsStr},
+    fs,
+    os::unix::{ffi::OsStrExt, fs::PermissionsExt},
+    path::{Path, PathBuf},
+    process::Command,
+    sync::OnceLock,
+};
+
+use anyhow::Context;
+
+use crate::elevate::Elevator;
+
+/// How Geph was installed, as far as updating goes.
+#[derive(Clone, Debug)]
+pub enum Install {
+    /// From our `.deb`; `exe` is where it put us.
+    Deb { exe: PathBuf },
+    /// An AppImage at this path.
+    AppImage(PathBuf),
+    /// Nothing we know how to update.
+    Unmanaged,
+}
+
+impl Install {
+    /// This process's installation, detected once.
+    pub fn current() -> &'static Install {
+        static CURRENT: OnceLock<Install> = OnceLock::new();
+        CURRENT.get_or_init(|| {
+            let install = Self::detect();
+            tracing::debug!(?install, "detected installation type");
+            install
+        })
+    }
+
+    fn detect() -> Install {
+        if let Some(image) = std::env::var_os("APPIMAGE").map(PathBuf::from)
+            && image.is_file()
+        {
+            return Install::AppImage(image);
+        }
+        let Ok(exe) = std::env::current_exe().and_then(fs::canonicalize) else {
+            return Install::Unmanaged;
+        };
+        let owned = Command::new("dpkg")
+            .arg("-S")
+            .arg(&exe)
+            .output()
+            .is_ok_and(|out| out.status.success());
+        if owned {
+            Install::Deb { exe }
+        } else {
+            Install::Unmanaged
+        }
+    }
+
+    /// Whether this is an installation we can update at all.
+    pub fn updatable(&self) -> bool {
+        !matches!(self, Install::Unmanaged)
+    }
+
+    /// Fail if an update couldn't be applied right now, so we don't download
+    /// one only to be unable to install it.
+    pub fn check_applicable(&self) -> anyhow::Result<()> {
+        match self {
+            Install::Deb { .. } => {
+                Elevator::detect().context(
+                    "no way to get administrator privileges to install the update was found",
+                )?;
+            }
+            Install::AppImage(image) => {
+                let dir = image.parent().context("AppImage path has no directory")?;
+                let dir = CString::new(dir.as_os_str().as_bytes())?;
+                if unsafe { libc::access(dir.as_ptr(), libc::W_OK) } != 0 {
+                    anyhow::bail!(
+                        "cannot replace the AppImage at {}: {}",
+                        image.display(),
+                        std::io::Error::last_os_error()
+                    );
+                }
+            }
+            Install::Unmanaged => {
+                anyhow::bail!("this copy of Geph was not installed in a way we can update")
+            }
+        }
+        Ok(())
+    }
+
+    /// Install the verified download at `path`, whose SHA-256 is `sha256`.
+    /// Blocks, possibly for as long as the user takes to authenticate.
+    pub fn apply(&self, path: &Path, sha256: &str) -> anyhow::Result<()> {
+        match self {
+            Install::Deb { .. } => {
+                let elevator = Elevator::detect().context(
+                    "no way to get administrator privileges to install the update was found",
+                )?;
+                elevator
+                    .run(
+                        "sh",
+                        &[
+                            "-c".as_ref(),
+                            INSTALL_DEB.as_ref(),
+                            "sh".as_ref(),
+                            path.as_os_str(),
+                            OsStr::new(sha256),
+                        ],
+                    )
+                    .context("installing the update package")
+            }
+            Install::AppImage(image) => replace_appimage(image, path, sha256),
+            Install::Unmanaged => {
+                anyhow::bail!("this copy of Geph was not installed in a way we can update")
+            }
+        }
+    }
+
+    /// Start the (now updated) installation once this process has exited. The new
+    /// instance can only take the single-instance port (main.rs) after we're
+    /// gone, and quits if it loses that race, so the helper waits for our pid to
+    /// disappear (bounded at ~10s) first.
+    pub fn relaunch(&self) {
+        let exe = match self {
+            Install::Deb { exe } => exe,
+            Install::AppImage(image) => image,
+            Install::Unmanaged => return,
+        };
+        const HANDOFF: &str = r#"for _ in $(seq 40); do
+    kill -0 "$1" 2>/dev/null || break
+    sleep 0.25
+done
+exec "$2" >/dev/null 2>&1"#;
+        let spawned = Command::new("sh")
+            .args(["-c", HANDOFF, "sh"])
+            .arg(std::process::id().to_string())
+            .arg(exe)
+            .spawn();
+        if let Err(err) = spawned {
+            tracing::warn!(
+                err = debug(err),
+                "could not schedule a relaunch after updating"
+            );
+        }
+    }
+}
+
+/// Runs as root: `sh -c INSTALL_DEB sh <deb> <sha256>`. apt resolves any new
+/// dependencies and allows going back to an older release (see channel.rs);
+/// plain dpkg does both as well as it can.
+const INSTALL_DEB: &str = r#"set -eu
+work=$(mktemp -d)
+trap 'rm -rf "$work"' EXIT
+cp -- "$1" "$work/update.deb"
+if ! printf '%s  %s\n' "$2" "$work/update.deb" | sha256sum -c --status -; then
+    echo "geph: the update package changed after it was verified; refusing to install" >&2
+    exit 1
+fi
+if command -v apt-get >/dev/null; then
+    DEBIAN_FRONTEND=noninteractive apt-get install -y --allow-downgrades "$work/update.deb"
+else
+    dpkg -i "$work/update.deb"
+fi"#;
+
+/// Copy `new` next to `image`, check it, and rename it over `image`.
+fn replace_appimage(image: &Path, new: &Path, sha256: &str) -> anyhow::Result<()> {
+    let mut name = image.file_name().unwrap_or_default().to_os_string();
+    name.push(".update");
+    let staged = image.with_file_name(name);
+    let result = (|| {
+        fs::copy(new, &staged).with_context(|| format!("copy to {}", staged.display()))?;
+        let (hash, _) = super::download::hash_file(&staged)?;
+        if hex::encode(hash.finalize()) != sha256 {
+            anyhow::bail!("the copied AppImage does not match the verified download");
+        }
+        fs::set_permissions(&staged, fs::Permissions::from_mode(0o755))?;
+        fs::File::open(&staged)?.sync_all()?;
+        fs::rename(&staged, image).with_context(|| format!("replace {}", image.display()))?;
+        Ok(())
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&staged);
+    }
+    result
+}
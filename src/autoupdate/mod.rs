@@ -0,0 +1,695 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::ErrorKind,
+    path::PathBuf,
+    process::exit,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use anyhow::Context;
+use rand::Rng;
+
+use semver::Version;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    dialog,
+    l10n::tr,
+    manager::{self, stop_daemon},
+    prefs::{self, UpdateChannel},
+};
+
+mod cache;
+mod channel;
+mod deferred;
+mod download;
+#[cfg(target_os = "linux")]
+mod linux;
+mod schedule;
+mod signing;
+mod source;
+mod status;
+#[cfg(test)]
+mod tests;
+
+pub use cache::{UpdateCacheInfo, clear_downloads, info as cache_info, set_keep_previous};
+pub use deferred::{
+    Deferral, InstallWhen, get as deferred_update, install_on_quit, observe_state as observe_tunnel,
+};
+pub use download::{DownloadProgress, progress as download_progress};
+pub use status::{UpdateStatus, get as update_status};
+
+use channel::Offer;
+use schedule::{STARTUP_GRACE, ScheduleState, Scheduler, SystemClock};
+use signing::KeyRing;
+use source::{EngineOrMirrors, ManifestSource};
+use status::Decisions;
+
+const UPDATE_MEAN_INTERVAL_HOURS: f64 = 6.0;
+const RETRY_DELAY_SECONDS: u64 = 600;
+const CACHE_FOLDER: &str = "geph5-dl";
+const METADATA_FILE: &str = "update-metadata.json";
+const DECISIONS_FILE: &str = "update-decisions.json";
+const SCHEDULE_FILE: &str = "update-schedule.json";
+const INSTALLED_FILE: &str = "update-installed.json";
+const PREVIOUS_FILE: &str = "update-previous.json";
+
+/// Set while a check (and the download it may start) is running, so a "check
+/// now" from the webview can't race the background loop.
+static CHECKING: AtomicBool = AtomicBool::new(false);
+
+/// Whether we update ourselves at all; see `unavailable`.
+pub fn enabled() -> bool {
+    unavailable().is_none()
+}
+
+/// Why we don't update ourselves, if we don't. DO NOT run the autoupdate logic
+/// on flatpak, where the store does it, nor on Linux installs we don't know how
+/// to replace (see linux.rs), nor in a build with no release key to check
+/// updates against (signing.rs), where every one would be refused. Otherwise
+/// it's good.
+fn unavailable() -> Option<&'static str> {
+    if std::env::var("FLATPAK_ID").is_ok() {
+        return Some("updates are managed by Flatpak");
+    }
+    if !KeyRing::embedded().has_trusted_key() {
+        tracing::warn!("update-keys.txt lists no trusted key; updates are disabled");
+        return Some("this build has no key to verify updates with");
+    }
+    #[cfg(target_os = "linux")]
+    if !linux::Install::current().updatable() {
+        return Some("this installation can't update itself; update it the way it was installed");
+    }
+    None
+}
+
+/// Background loop that checks for updates whenever the persisted schedule
+/// (schedule.rs) says one is due, downloading any it finds and recording
+/// metadata so we can prompt on the next startup.
+pub async fn download_update_loop() {
+    tokio::time::sleep(STARTUP_GRACE).await;
+    loop {
+        let delay = Scheduler::new(SystemClock, ScheduleState::load()).until_due();
+        if !delay.is_zero() {
+            tracing::debug!(delay = debug(delay), "delay set for update checking");
+            // A manual check may move the schedule meanwhile, so look again after.
+            tokio::time::sleep(delay).await;
+            continue;
+        }
+        match check().await {
+            Ok(reason) => tracing::debug!(?reason, "update check finished"),
+            Err(err) => tracing::debug!(err = debug(err), "failed to cache update"),
+        }
+        // If the schedule couldn't be saved it still says "due"; don't spin.
+        if Scheduler::new(SystemClock, ScheduleState::load())
+            .until_due()
+            .is_zero()
+        {
+            tokio::time::sleep(Duration::from_secs(RETRY_DELAY_SECONDS)).await;
+        }
+    }
+}
+
+/// On startup, prompt the user if we already downloaded an update previously.
+pub async fn prompt_cached_update_if_available() -> anyhow::Result<()> {
+    if let Err(err) = cache::housekeep() {
+        tracing::debug!(err = debug(err), "update cache housekeeping failed");
+    }
+    let updater = Updater::system()?;
+    let Some(metadata) = updater.load_metadata()? else {
+        tracing::debug!("no cached update metadata; skipping prompt");
+        return Ok(());
+    };
+    tracing::debug!(version = %metadata.version, path = %metadata.download_path.display(), "cached update metadata found");
+
+    let offer = match updater.offer(&metadata) {
+        Ok(Some(offer)) => offer,
+        Ok(None) => return Ok(()),
+        Err(err) => {
+            tracing::debug!(err = debug(err), "invalid cached update metadata");
+            let _ = updater.clear_metadata();
+            return Ok(());
+        }
+    };
+
+    if !metadata.download_path.exists() {
+        return Ok(());
+    }
+
+    // The cache is only as trustworthy as the user's own files; check the
+    // installer again right before offering to run it.
+    if let Err(err) = updater.verify_cached(&metadata).await {
+        tracing::warn!(
+            err = debug(err),
+            "cached update failed verification; discarding it"
+        );
+        let _ = fs::remove_file(&metadata.download_path);
+        let _ = updater.clear_metadata();
+        return Ok(());
+    }
+
+    if !Decisions::load().allows(&metadata.version) {
+        tracing::debug!(version = %metadata.version, "update skipped or postponed by the user");
+        return Ok(());
+    }
+    status::set(UpdateStatus::Ready {
+        version: metadata.version.clone(),
+        notes: metadata.notes.clone(),
+        downgrade: offer == Offer::Downgrade,
+    });
+    prompt_update(&metadata, offer).await
+}
+
+/// Check for an update right away, at the user's request. Returns immediately;
+/// progress and the outcome arrive as `geph_update_status` events.
+pub fn check_now() -> anyhow::Result<()> {
+    if let Some(reason) = unavailable() {
+        anyhow::bail!("{reason}");
+    }
+    // Asking is as good as being reminded.
+    Decisions::clear_reminder()?;
+    geph5_rt::spawn(async {
+        if let Err(err) = check().await {
+            tracing::debug!(err = debug(err), "manual update check failed");
+        }
+    })
+    .detach();
+    Ok(())
+}
+
+/// Install the downloaded update now: the webview's counterpart to "Yes" in the
+/// startup prompt. Only returns on error; otherwise the GUI exits for the
+/// installer.
+pub async fn install_now() -> anyhow::Result<()> {
+    let updater = Updater::system()?;
+    let metadata = updater.pending()?;
+    updater.verify_cached(&metadata).await?;
+    install(&metadata).await
+}
+
+/// Install the downloaded update `when` it won't drop the tunnel, instead of
+/// now (see deferred.rs).
+pub fn defer_install(when: InstallWhen) -> anyhow::Result<()> {
+    let metadata = Updater::system()?.pending()?;
+    tracing::info!(version = %metadata.version, ?when, "update installation deferred");
+    deferred::set(Some(Deferral {
+        version: metadata.version.clone(),
+        when,
+    }));
+    status::set(UpdateStatus::Deferred {
+        version: metadata.version,
+        when,
+    });
+    Ok(())
+}
+
+/// Install the update `deferral` was made for, if it's still the one cached.
+/// Only returns on error.
+async fn install_deferred(deferral: &Deferral) -> anyhow::Result<()> {
+    let metadata = Updater::system()?
+        .load_metadata()?
+        .context("no update has been downloaded")?;
+    if metadata.version != deferral.version {
+        anyhow::bail!(
+            "the deferred update {} was replaced by {}",
+            deferral.version,
+            metadata.version
+        );
+    }
+    install_now().await
+}
+
+/// A deferred installation went wrong in the background, with nobody waiting on
+/// it; report it to the webview and go back to offering the update.
+fn deferred_install_failed(err: anyhow::Error) {
+    tracing::warn!(err = debug(&err), "deferred update installation failed");
+    status::set(UpdateStatus::Failed {
+        error: format!("{err:#}"),
+    });
+    if let Some(status) = cached_status() {
+        status::set(status);
+    }
+}
+
+/// Follow `channel` from now on. An update downloaded from the old channel is
+/// forgotten, and the new one is checked right away.
+pub fn set_channel(channel: UpdateChannel) -> anyhow::Result<()> {
+    if prefs::get().update_channel == channel {
+        return Ok(());
+    }
+    prefs::update(|p| p.update_channel = channel)?;
+    tracing::info!(channel = channel.name(), "update channel changed");
+    deferred::set(None);
+    Updater::system()?.clear_metadata()?;
+    status::set(UpdateStatus::Idle);
+    if enabled() {
+        check_now()?;
+    }
+    Ok(())
+}
+
+/// Don't offer the pending update again for a while.
+pub fn remind_later() -> anyhow::Result<()> {
+    Decisions::remind_later()?;
+    deferred::set(None);
+    status::set(UpdateStatus::Idle);
+    Ok(())
+}
+
+/// Never offer the pending update's version again. Later versions still are.
+pub fn skip_version() -> anyhow::Result<()> {
+    let metadata = Updater::system()?
+        .load_metadata()?
+        .context("no update has been downloaded")?;
+    Decisions::skip(&metadata.version)?;
+    deferred::set(None);
+    tracing::info!(version = %metadata.version, "update skipped by the user");
+    status::set(UpdateStatus::Idle);
+    Ok(())
+}
+
+/// `ensure_update_cached`, reporting each stage as a status and recording the
+/// outcome in the schedule.
+async fn check() -> anyhow::Result<CacheResult> {
+    if CHECKING.swap(true, Ordering::SeqCst) {
+        anyhow::bail!("an update check is already running");
+    }
+    status::set(UpdateStatus::Checking);
+    let mut schedule = Scheduler::new(SystemClock, ScheduleState::load());
+    schedule.attempted();
+    let _ = schedule.state().save();
+    let result = ensure_update_cached().await;
+    CHECKING.store(false, Ordering::SeqCst);
+
+    let jitter = rand::thread_rng().gen_range(0.0..1.0);
+    match &result {
+        Ok(_) => schedule.succeeded(jitter),
+        Err(_) => schedule.failed(jitter),
+    }
+    if let Err(err) = schedule.state().save() {
+        tracing::debug!(err = debug(err), "could not save the update schedule");
+    }
+    // A failed check doesn't make an update we already have any less ready.
+    status::set(cached_status().unwrap_or_else(|| match &result {
+        Ok(_) => UpdateStatus::Idle,
+        Err(err) => UpdateStatus::Failed {
+            error: format!("{err:#}"),
+        },
+    }));
+    result
+}
+
+/// The status a cached update calls for, if one worth installing is waiting:
+/// `Deferred` if the user already accepted it for later, otherwise `Ready`,
+/// unless they skipped or postponed it.
+fn cached_status() -> Option<UpdateStatus> {
+    let updater = Updater::system().ok()?;
+    let metadata = updater.load_metadata().ok()??;
+    let offer = updater.offer(&metadata).ok()??;
+    if !metadata.download_path.exists() {
+        return None;
+    }
+    match deferred::get() {
+        Some(deferral) if deferral.version == metadata.version => {
+            return Some(UpdateStatus::Deferred {
+                version: metadata.version,
+                when: deferral.when,
+            });
+        }
+        // Accepted for a version that has since been superseded: ask again.
+        Some(_) => deferred::set(None),
+        None => {}
+    }
+    Some(if Decisions::load().allows(&metadata.version) {
+        UpdateStatus::Ready {
+            version: metadata.version,
+            notes: metadata.notes,
+            downgrade: offer == Offer::Downgrade,
+        }
+    } else {
+        UpdateStatus::Idle
+    })
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum CacheResult {
+    AlreadyCurrent,
+    CachedFresh,
+    AlreadyCached,
+}
+
+/// Check this installation can take an update, then cache one if there is any.
+async fn ensure_update_cached() -> anyhow::Result<CacheResult> {
+    #[cfg(target_os = "linux")]
+    linux::Install::current().check_applicable()?;
+    Updater::system()?.ensure_cached().await
+}
+
+/// What a check runs against: where the manifest comes from, which keys to
+/// trust, where downloads and their metadata go, and which version and channel
+/// we're updating from and along. `Updater::system()` is the real thing; the
+/// tests (tests.rs) put a local server, their own keys and a scratch directory in
+/// its place.
+struct Updater<S = EngineOrMirrors> {
+    source: S,
+    keys: KeyRing,
+    root: PathBuf,
+    current: Version,
+    channel: UpdateChannel,
+}
+
+impl Updater {
+    fn system() -> anyhow::Result<Self> {
+        Ok(Updater {
+            source: EngineOrMirrors,
+            keys: KeyRing::embedded().clone(),
+            root: cache_root()?,
+            current: current_version()?,
+            channel: prefs::get().update_channel,
+        })
+    }
+}
+
+impl<S> Updater<S> {
+    fn metadata_path(&self) -> PathBuf {
+        self.root.join(METADATA_FILE)
+    }
+
+    fn write_metadata(&self, metadata: &UpdateMetadata) -> anyhow::Result<()> {
+        let bytes = serde_json::to_vec(metadata)?;
+        fs::write(self.metadata_path(), bytes)?;
+        Ok(())
+    }
+
+    fn load_metadata(&self) -> anyhow::Result<Option<UpdateMetadata>> {
+        match fs::read(self.metadata_path()) {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn clear_metadata(&self) -> anyhow::Result<()> {
+        match fs::remove_file(self.metadata_path()) {
+            Ok(_) => Ok(()),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// What installing the cached update would do for this version on the
+    /// user's channel, if it's worth installing at all. An update cached from
+    /// another channel's track isn't.
+    fn offer(&self, metadata: &UpdateMetadata) -> anyhow::Result<Option<Offer>> {
+        if metadata.track != channel::track(self.channel) {
+            return Ok(None);
+        }
+        Ok(channel::offer(
+            &Version::parse(&metadata.version)?,
+            &self.current,
+            self.channel,
+        ))
+    }
+
+    /// The cached update, if there is one worth installing.
+    fn pending(&self) -> anyhow::Result<UpdateMetadata> {
+        let metadata = self
+            .load_metadata()?
+            .context("no update has been downloaded")?;
+        if self.offer(&metadata)?.is_none() {
+            anyhow::bail!("the downloaded update does not apply to this version or channel");
+        }
+        Ok(metadata)
+    }
+
+    /// Re-hash a cached installer and re-check its detached signature.
+    async fn verify_cached(&self, metadata: &UpdateMetadata) -> anyhow::Result<()> {
+        let hash = read_file_sha256(metadata.download_path.clone()).await?;
+        if hash != metadata.sha256 {
+            anyhow::bail!("cached file hash mismatch");
+        }
+        self.keys.verify_artifact(&hash, &metadata.signature)?;
+        Ok(())
+    }
+}
+
+impl<S: ManifestSource> Updater<S> {
+    async fn ensure_cached(&self) -> anyhow::Result<CacheResult> {
+        let source::Manifest {
+            manifest,
+            base_url,
+            client,
+        } = self.source.fetch().await?;
+        let track = channel::track(self.channel);
+        let entry: ManifestEntry = serde_json::from_value(
+            manifest
+                .get(&track)
+                .with_context(|| format!("no {track} track in the update manifest"))?
+                .clone(),
+        )?;
+        let url = format!("{base_url}/{track}/{}/{}", entry.version, entry.filename);
+        tracing::debug!(version = %entry.version, url, "update manifest fetched");
+        let key = self.keys.verify_entry(
+            &track,
+            &entry.version,
+            &entry.filename,
+            &entry.sha256,
+            &entry.signatures,
+        )?;
+        tracing::debug!(key, "update manifest entry signature verified");
+
+        let current_version = &self.current;
+        let manifest_version = Version::parse(&entry.version)?;
+
+        let Some(offer) = channel::offer(&manifest_version, current_version, self.channel) else {
+            tracing::debug!(
+                %manifest_version,
+                %current_version,
+                "already running latest version"
+            );
+            let _ = self.clear_metadata();
+            return Ok(CacheResult::AlreadyCurrent);
+        };
+        tracing::debug!(?offer, %manifest_version, %current_version, "update available");
+
+        // Fetched and checked before the (large) download, so a tampered release
+        // costs nothing but this request.
+        let signature = client
+            .get(format!("{url}.sig"))
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        let key = self.keys.verify_artifact(&entry.sha256, &signature)?;
+        tracing::debug!(key, "update artifact signature verified");
+
+        let hash_path = self.root.join(&entry.sha256);
+        fs::create_dir_all(&hash_path)?;
+        let download_path = hash_path.join(&entry.filename);
+
+        let need_download = !download_path.exists()
+            || read_file_sha256(download_path.clone()).await? != entry.sha256;
+
+        if need_download {
+            tracing::info!(
+                "Downloading update from {} to {}",
+                url,
+                download_path.display()
+            );
+
+            download::fetch(&client, &url, &download_path, &entry.sha256, &entry.version).await?;
+        }
+
+        self.write_metadata(&UpdateMetadata {
+            version: entry.version,
+            sha256: entry.sha256,
+            filename: entry.filename,
+            download_path,
+            signature,
+            notes: entry.notes,
+            track,
+        })?;
+        tracing::debug!("cached update metadata written successfully");
+        if let Err(err) = cache::prune(self) {
+            tracing::debug!(err = debug(err), "could not prune the update cache");
+        }
+
+        Ok(if need_download {
+            CacheResult::CachedFresh
+        } else {
+            CacheResult::AlreadyCached
+        })
+    }
+}
+
+/// Offer a verified, cached update in a native dialog: install it (while
+/// connected, now or at the next safe moment), remind the user later, or skip
+/// this version.
+async fn prompt_update(metadata: &UpdateMetadata, offer: Offer) -> anyhow::Result<()> {
+    // The update prompt is a native modal dialog. On macOS, `rfd` refuses to show
+    // a dialog from any thread other than the main one while the app isn't yet a
+    // windowed foreground app — so we must NOT offload this to a blocking-pool
+    // thread (e.g. `spawn_blocking`), which is exactly what used to panic here.
+    //
+    // The sole caller, `prompt_cached_update_if_available`, runs via `block_on` at
+    // startup, before the main window exists: on the main thread on macOS, and on
+    // the startup worker thread behind the splash (splash.rs) elsewhere, where
+    // native dialogs work from any thread. Either way it intentionally blocks
+    // startup until the user answers.
+    let body = match offer {
+        Offer::Upgrade => tr("update.body"),
+        Offer::Downgrade => tr("update.downgrade_body"),
+    };
+    let mut description = body.replace("{}", &metadata.version);
+    if let Some(notes) = metadata.notes.as_deref().filter(|notes| !notes.is_empty()) {
+        description.push_str("\n\n");
+        description.push_str(notes);
+    }
+    match dialog::show(
+        dialog::Level::Info,
+        tr("update.title"),
+        &description,
+        &[tr("update.install"), tr("update.later"), tr("update.skip")],
+    ) {
+        Some(0) => {
+            if manager::manager_state().await == Some(true) {
+                match dialog::show(
+                    dialog::Level::Info,
+                    tr("update.title"),
+                    tr("update.connected_body"),
+                    &[
+                        tr("update.install_disconnect"),
+                        tr("update.when_disconnected"),
+                        tr("update.on_quit"),
+                    ],
+                ) {
+                    Some(0) => {}
+                    Some(1) => return defer_install(InstallWhen::Disconnected),
+                    Some(2) => return defer_install(InstallWhen::Quit),
+                    // Dismissed: stays on offer, as below.
+                    _ => return Ok(()),
+                }
+            }
+            // Declining the password prompt lands here too; that's no reason
+            // not to start.
+            if let Err(err) = install(metadata).await {
+                tracing::warn!(err = debug(&err), "update installation failed");
+                dialog::show(
+                    dialog::Level::Error,
+                    tr("update.title"),
+                    &tr("update.install_failed").replace("{}", &format!("{err:#}")),
+                    &[tr("common.ok")],
+                );
+            }
+            Ok(())
+        }
+        Some(1) => remind_later(),
+        Some(2) => skip_version(),
+        // Dismissed: no decision, so it stays on offer in the webview.
+        _ => Ok(()),
+    }
+}
+
+/// Launch the installer for a verified update (on Linux, install it and
+/// arrange a relaunch), stop the tunnel, and exit. Returns only on error, with
+/// everything still running.
+async fn install(metadata: &UpdateMetadata) -> anyhow::Result<()> {
+    let path = &metadata.download_path;
+    #[cfg(target_os = "windows")]
+    {
+        // On Windows, just execute the installer.
+        std::process::Command::new(path).arg("/SILENT").spawn()?;
+    }
+    #[cfg(target_os = "macos")]
+    {
+        // On macOS, open the .dmg or .pkg file.
+        std::process::Command::new("open").arg(path).spawn()?;
+    }
+    #[cfg(target_os = "linux")]
+    {
+        let (path, sha256) = (path.clone(), metadata.sha256.clone());
+        geph5_rt::spawn_blocking(move || linux::Install::current().apply(&path, &sha256)).await?;
+    }
+
+    // Stop the tunnel
+    stop_daemon().await?;
+
+    #[cfg(target_os = "linux")]
+    linux::Install::current().relaunch();
+
+    // Exit the application
+    tracing::info!("Exiting for update installation");
+    exit(0);
+}
+
+fn cache_root() -> anyhow::Result<PathBuf> {
+    let path = dirs::cache_dir()
+        .context("no cache dir in the system")?
+        .join(CACHE_FOLDER);
+    fs::create_dir_all(&path)?;
+    Ok(path)
+}
+
+/// Delete every downloaded update along with the metadata pointing at them.
+pub fn clear_cache() -> anyhow::Result<()> {
+    let path = dirs::cache_dir()
+        .context("no cache dir in the system")?
+        .join(CACHE_FOLDER);
+    match fs::remove_dir_all(path) {
+        Ok(_) => Ok(()),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn current_version() -> anyhow::Result<Version> {
+    Version::parse(
+        option_env!("VERSION")
+            .unwrap_or("0.0.0")
+            .trim_start_matches('v'),
+    )
+    .map_err(|e| e.into())
+}
+
+async fn read_file_sha256(fname: PathBuf) -> anyhow::Result<String> {
+    geph5_rt::spawn_blocking(move || {
+        let (hash, _) = download::hash_file(&fname)?;
+        anyhow::Ok(hex::encode(hash.finalize()))
+    })
+    .await
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ManifestEntry {
+    version: String,
+    sha256: String,
+    filename: String,
+    /// Key id → hex ed25519 signature over this entry (see signing.rs).
+    #[serde(default)]
+    signatures: BTreeMap<String, String>,
+    /// What's new, shown as plain text next to the offer. Not signed, so it must
+    /// never be treated as anything but text.
+    #[serde(default)]
+    notes: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct UpdateMetadata {
+    version: String,
+    sha256: String,
+    filename: String,
+    download_path: PathBuf,
+    /// The artifact's detached `.sig` file, kept to re-check it before running.
+    /// Empty for metadata cached before updates were signed, which then fails.
+    #[serde(default)]
+    signature: String,
+    #[serde(default)]
+    notes: Option<String>,
+    /// The manifest track it came from; metadata from before channels were
+    /// selectable has none, and is re-fetched.
+    #[serde(default)]
+    track: String,
+}
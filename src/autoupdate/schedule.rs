@@ -0,0 +1,212 @@
+//! When to check for updates next.
+//!
+//! The check loop used to sleep a fresh random delay (averaging six hours) at
+//! every start and then check. A GUI that's restarted more often than that, which
+//! is most of them, almost never got to check; and a failed check waited just as
+//! long as a successful one. So the schedule is persisted in
+//! `update-schedule.json`, next to the download cache's other files:
+//!
+//!   * after a successful check, the next one is due in about
+//!     `UPDATE_MEAN_INTERVAL_HOURS` (±25%, so clients don't all arrive at once);
+//!   * after a failure, in `RETRY_DELAY_SECONDS`, doubling with each further
+//!     failure up to the normal interval, with half of it random;
+//!   * a check that came due while the GUI wasn't running happens shortly after
+//!     the next start (the loop waits `STARTUP_GRACE` so the tunnel can come up).
+//!
+//! A "check now" from the webview goes through the same bookkeeping, so the loop
+//! just sleeps until whatever is due and looks again. Times are wall-clock Unix
+//! seconds from a `Clock`, which the tests replace.
+
+use std::{
+    fs,
+    io::ErrorKind,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{RETRY_DELAY_SECONDS, SCHEDULE_FILE, UPDATE_MEAN_INTERVAL_HOURS, cache_root};
+
+/// How long after startup an overdue check waits.
+pub const STARTUP_GRACE: Duration = Duration::from_secs(60);
+
+/// The time, in Unix seconds.
+pub trait Clock {
+    fn now(&self) -> u64;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+    }
+}
+
+/// What's persisted between runs.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScheduleState {
+    pub last_success: Option<u64>,
+    pub last_attempt: Option<u64>,
+    /// Failed checks since the last success.
+    pub failures: u32,
+    /// When the next check is due; `None` (never checked) means now.
+    pub next_check: Option<u64>,
+}
+
+impl ScheduleState {
+    /// The saved schedule, or a fresh one (due right away) if there's none or
+    /// it's unreadable.
+    pub fn load() -> Self {
+        let Ok(root) = cache_root() else {
+            return Self::default();
+        };
+        match fs::read(root.join(SCHEDULE_FILE)) {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_default(),
+            Err(err) => {
+                if err.kind() != ErrorKind::NotFound {
+                    tracing::debug!(err = debug(err), "could not read update schedule");
+                }
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        fs::write(cache_root()?.join(SCHEDULE_FILE), serde_json::to_vec(self)?)?;
+        Ok(())
+    }
+}
+
+pub struct Scheduler<C> {
+    clock: C,
+    state: ScheduleState,
+}
+
+impl<C: Clock> Scheduler<C> {
+    pub fn new(clock: C, state: ScheduleState) -> Self {
+        Self { clock, state }
+    }
+
+    pub fn state(&self) -> &ScheduleState {
+        &self.state
+    }
+
+    /// How long until the next check is due (zero if it's overdue). Never more
+    /// than a little over the normal interval, so a clock that jumped backwards
+    /// can't postpone checks for good.
+    pub fn until_due(&self) -> Duration {
+        let Some(next) = self.state.next_check else {
+            return Duration::ZERO;
+        };
+        Duration::from_secs(next.saturating_sub(self.clock.now())).min(interval().mul_f64(1.25))
+    }
+
+    pub fn attempted(&mut self) {
+        self.state.last_attempt = Some(self.clock.now());
+    }
+
+    /// Record a successful check. `jitter` is uniform in `[0, 1)`.
+    pub fn succeeded(&mut self, jitter: f64) {
+        let now = self.clock.now();
+        self.state.last_success = Some(now);
+        self.state.failures = 0;
+        self.state.next_check = Some(now + interval().mul_f64(0.75 + 0.5 * jitter).as_secs());
+    }
+
+    /// Record a failed check. `jitter` is uniform in `[0, 1)`.
+    pub fn failed(&mut self, jitter: f64) {
+        let now = self.clock.now();
+        self.state.failures = self.state.failures.saturating_add(1);
+        let backoff = Duration::from_secs(RETRY_DELAY_SECONDS)
+            .saturating_mul(1 << (self.state.failures - 1).min(16))
+            .min(interval());
+        self.state.next_check = Some(now + backoff.mul_f64(0.5 + 0.5 * jitter).as_secs());
+    }
+}
+
+fn interval() -> Duration {
+    Duration::from_secs_f64(UPDATE_MEAN_INTERVAL_HOURS * 3600.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc};
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct FakeClock(Rc<Cell<u64>>);
+
+    impl Clock for FakeClock {
+        fn now(&self) -> u64 {
+            self.0.get()
+        }
+    }
+
+    impl FakeClock {
+        fn advance(&self, by: Duration) {
+            self.0.set(self.0.get() + by.as_secs());
+        }
+    }
+
+    fn scheduler(state: ScheduleState) -> (Scheduler<FakeClock>, FakeClock) {
+        let clock = FakeClock::default();
+        clock.0.set(1_000_000_000);
+        (Scheduler::new(clock.clone(), state), clock)
+    }
+
+    #[test]
+    fn never_checked_is_due_now() {
+        let (scheduler, _) = scheduler(ScheduleState::default());
+        assert_eq!(scheduler.until_due(), Duration::ZERO);
+    }
+
+    #[test]
+    fn success_waits_about_the_interval() {
+        let (mut scheduler, clock) = scheduler(ScheduleState::default());
+        scheduler.attempted();
+        scheduler.succeeded(0.5);
+        assert_eq!(scheduler.until_due(), interval());
+        assert_eq!(scheduler.state().last_success, Some(clock.now()));
+        assert_eq!(scheduler.state().last_attempt, Some(clock.now()));
+
+        // Restarted long after it came due: overdue, not pushed back.
+        clock.advance(interval() * 3);
+        let scheduler = Scheduler::new(clock.clone(), scheduler.state().clone());
+        assert_eq!(scheduler.until_due(), Duration::ZERO);
+    }
+
+    #[test]
+    fn failures_back_off_up_to_the_interval() {
+        let (mut scheduler, _) = scheduler(ScheduleState::default());
+        let retry = Duration::from_secs(RETRY_DELAY_SECONDS);
+        let mut delays = vec![];
+        for _ in 0..12 {
+            scheduler.failed(0.999_999);
+            delays.push(scheduler.until_due());
+        }
+        assert_eq!(delays[0], retry - Duration::from_secs(1));
+        assert!(delays.windows(2).all(|w| w[0] <= w[1]));
+        assert!(delays[1] > delays[0] * 3 / 2);
+        assert!(*delays.last().unwrap() <= interval());
+        // The random half: the minimum jitter halves the wait.
+        scheduler.failed(0.0);
+        assert_eq!(scheduler.until_due(), interval() / 2);
+
+        scheduler.succeeded(0.0);
+        assert_eq!(scheduler.state().failures, 0);
+    }
+
+    #[test]
+    fn clock_going_backwards_does_not_stall_checks() {
+        let (mut scheduler, clock) = scheduler(ScheduleState::default());
+        scheduler.succeeded(0.5);
+        clock.0.set(clock.now() - 365 * 24 * 3600);
+        assert!(scheduler.until_due() <= interval().mul_f64(1.25));
+    }
+}
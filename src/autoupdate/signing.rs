@@ -0,0 +1,262 @@
+//! Signature checks for updates.
+//!
+//! The update manifest reaches us through the daemon, and the artifact's
+//! `sha256` arrives in the same manifest as its URL, so a hash check alone only
+//! proves the download matches whatever the manifest said. Anyone who can tamper
+//! with the manifest's path could pick the installer that `install` then
+//! launches (silently, on Windows). So both halves are signed with ed25519 keys
+//! whose public parts are compiled in from `update-keys.txt`:
+//!
+//!   * each manifest entry carries `signatures: {key id: hex signature}` over
+//!     `geph-update-v1\n{track}\n{version}\n{filename}\n{sha256}`, binding the
+//!     artifact to the track and version it is offered as;
+//!   * each artifact has a detached `<artifact>.sig` next to it, one
+//!     `<key id> <hex signature>` per line, over
+//!     `geph-update-artifact-v1\n{sha256}` (the digest, so the file never has to
+//!     be held in memory to check it).
+//!
+//! One valid signature from a trusted key is enough for each. That's what makes
+//! rotation work: releases are signed with both the outgoing and the incoming key
+//! for a while, binaries that only know the old key keep updating, and once no
+//! supported binary lacks the new key the old one is dropped from signing. A key
+//! marked `revoked` in `update-keys.txt` is never accepted again, even if it's
+//! the only one that signed.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::LazyLock,
+};
+
+use anyhow::Context;
+use ed25519_dalek::{Signature, VerifyingKey};
+
+const MANIFEST_CONTEXT: &str = "geph-update-v1";
+const ARTIFACT_CONTEXT: &str = "geph-update-artifact-v1";
+
+static EMBEDDED: LazyLock<KeyRing> = LazyLock::new(|| {
+    KeyRing::parse(include_str!("update-keys.txt")).expect("update-keys.txt is malformed")
+});
+
+/// The public keys updates may be signed with.
+#[derive(Clone)]
+pub struct KeyRing {
+    trusted: BTreeMap<String, VerifyingKey>,
+    revoked: BTreeSet<String>,
+}
+
+impl KeyRing {
+    /// The keys compiled into this binary.
+    pub fn embedded() -> &'static KeyRing {
+        &EMBEDDED
+    }
+
+    /// Whether any key could vouch for an update at all. An empty ring refuses
+    /// everything.
+    pub fn has_trusted_key(&self) -> bool {
+        !self.trusted.is_empty()
+    }
+
+    /// Parse `update-keys.txt`: one `<key id> <hex public key> [revoked]` per
+    /// line; blank lines and `#` comments are ignored.
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let mut ring = KeyRing {
+            trusted: BTreeMap::new(),
+            revoked: BTreeSet::new(),
+        };
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (id, key, revoked) = match fields[..] {
+                [id, key] => (id, key, false),
+                [id, key, "revoked"] => (id, key, true),
+                _ => anyhow::bail!(
+                    "line {}: expected `<key id> <hex key> [revoked]`",
+                    number + 1
+                ),
+            };
+            let key = parse_key(key).with_context(|| format!("line {}: key {id}", number + 1))?;
+            if revoked {
+                ring.revoked.insert(id.to_string());
+            } else {
+                ring.trusted.insert(id.to_string(), key);
+            }
+        }
+        Ok(ring)
+    }
+
+    /// Check a manifest entry's signatures. Returns the id of the key that
+    /// vouched for it.
+    pub fn verify_entry(
+        &self,
+        track: &str,
+        version: &str,
+        filename: &str,
+        sha256: &str,
+        signatures: &BTreeMap<String, String>,
+    ) -> anyhow::Result<&str> {
+        let message = format!(
+            "{MANIFEST_CONTEXT}\n{track}\n{version}\n{filename}\n{}",
+            sha256.to_ascii_lowercase()
+        );
+        self.verify(
+            message.as_bytes(),
+            signatures
+                .iter()
+                .map(|(id, sig)| (id.as_str(), sig.as_str())),
+        )
+        .context("the update manifest entry is not signed by a trusted key")
+    }
+
+    /// Check an artifact's detached `.sig` file against its SHA-256 digest (hex).
+    /// Returns the id of the key that vouched for it.
+    pub fn verify_artifact(&self, sha256: &str, sig_file: &str) -> anyhow::Result<&str> {
+        let message = format!("{ARTIFACT_CONTEXT}\n{}", sha256.to_ascii_lowercase());
+        let signatures = sig_file.lines().filter_map(|line| {
+            let line = line.split('#').next().unwrap_or("").trim();
+            line.split_once(char::is_whitespace)
+                .map(|(id, sig)| (id, sig.trim()))
+        });
+        self.verify(message.as_bytes(), signatures)
+            .context("the update artifact is not signed by a trusted key")
+    }
+
+    /// The first `(key id, hex signature)` pair that is a valid signature of
+    /// `message` by a trusted, unrevoked key.
+    fn verify<'a>(
+        &self,
+        message: &[u8],
+        signatures: impl Iterator<Item = (&'a str, &'a str)>,
+    ) -> anyhow::Result<&str> {
+        let mut tried = vec![];
+        for (id, sig) in signatures {
+            if self.revoked.contains(id) {
+                tracing::warn!(key = id, "ignoring a signature by a revoked update key");
+                tried.push(format!("{id} (revoked)"));
+                continue;
+            }
+            let Some((trusted_id, key)) = self.trusted.get_key_value(id) else {
+                tried.push(format!("{id} (unknown)"));
+                continue;
+            };
+            match parse_signature(sig).and_then(|sig| Ok(key.verify_strict(message, &sig)?)) {
+                Ok(()) => return Ok(trusted_id),
+                Err(err) => tried.push(format!("{id} ({err})")),
+            }
+        }
+        if tried.is_empty() {
+            anyhow::bail!("no signatures")
+        }
+        anyhow::bail!("no valid signature: {}", tried.join(", "))
+    }
+}
+
+fn parse_key(hex_key: &str) -> anyhow::Result<VerifyingKey> {
+    let bytes: [u8; 32] = hex::decode(hex_key)?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("expected 32 bytes"))?;
+    Ok(VerifyingKey::from_bytes(&bytes)?)
+}
+
+fn parse_signature(hex_sig: &str) -> anyhow::Result<Signature> {
+    let bytes: [u8; 64] = hex::decode(hex_sig)?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("expected 64 bytes"))?;
+    Ok(Signature::from_bytes(&bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::{Signer, SigningKey};
+
+    use super::*;
+
+    const SHA: &str = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+
+    fn key(seed: u8) -> SigningKey {
+        SigningKey::from_bytes(&[seed; 32])
+    }
+
+    fn ring(lines: &[(&str, u8, bool)]) -> KeyRing {
+        let text: String = lines
+            .iter()
+            .map(|(id, seed, revoked)| {
+                format!(
+                    "{id} {}{}\n",
+                    hex::encode(key(*seed).verifying_key().as_bytes()),
+                    if *revoked { " revoked" } else { "" }
+                )
+            })
+            .collect();
+        KeyRing::parse(&text).unwrap()
+    }
+
+    fn sign_entry(seed: u8, version: &str) -> String {
+        let message = format!("{MANIFEST_CONTEXT}\nlinux-stable\n{version}\ngeph.deb\n{SHA}");
+        hex::encode(key(seed).sign(message.as_bytes()).to_bytes())
+    }
+
+    fn sign_artifact(seed: u8) -> String {
+        let message = format!("{ARTIFACT_CONTEXT}\n{SHA}");
+        hex::encode(key(seed).sign(message.as_bytes()).to_bytes())
+    }
+
+    #[test]
+    fn embedded_keys_parse() {
+        let _ = KeyRing::embedded();
+    }
+
+    #[test]
+    fn accepts_a_signed_entry() {
+        let ring = ring(&[("2025a", 1, false)]);
+        let sigs = BTreeMap::from([("2025a".to_string(), sign_entry(1, "5.1.0"))]);
+        let id = ring
+            .verify_entry("linux-stable", "5.1.0", "geph.deb", SHA, &sigs)
+            .unwrap();
+        assert_eq!(id, "2025a");
+    }
+
+    #[test]
+    fn refuses_unsigned_and_altered_entries() {
+        let ring = ring(&[("2025a", 1, false)]);
+        assert!(
+            ring.verify_entry("linux-stable", "5.1.0", "geph.deb", SHA, &BTreeMap::new())
+                .is_err()
+        );
+        // Signed for 5.1.0, offered as 5.2.0.
+        let sigs = BTreeMap::from([("2025a".to_string(), sign_entry(1, "5.1.0"))]);
+        assert!(
+            ring.verify_entry("linux-stable", "5.2.0", "geph.deb", SHA, &sigs)
+                .is_err()
+        );
+        // Signed by a key we don't know, under a trusted key's id.
+        let sigs = BTreeMap::from([("2025a".to_string(), sign_entry(9, "5.1.0"))]);
+        assert!(
+            ring.verify_entry("linux-stable", "5.1.0", "geph.deb", SHA, &sigs)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn rotation_accepts_either_key() {
+        let ring = ring(&[("old", 1, false), ("new", 2, false)]);
+        let sig_file = format!("unknown {}\nnew {}\n", sign_artifact(3), sign_artifact(2));
+        assert_eq!(ring.verify_artifact(SHA, &sig_file).unwrap(), "new");
+    }
+
+    #[test]
+    fn revoked_keys_are_refused() {
+        let ring = ring(&[("old", 1, true), ("new", 2, false)]);
+        let sig_file = format!("old {}\n", sign_artifact(1));
+        assert!(ring.verify_artifact(SHA, &sig_file).is_err());
+    }
+
+    #[test]
+    fn artifact_signature_is_not_an_entry_signature() {
+        let ring = ring(&[("2025a", 1, false)]);
+        let sig_file = format!("2025a {}\n", sign_entry(1, "5.1.0"));
+        assert!(ring.verify_artifact(SHA, &sig_file).is_err());
+    }
+}
@@ -0,0 +1,194 @@
+//! Where update manifests come from.
+//!
+//! Normally the engine fetches the manifest for us: `get_update_manifest` over
+//! its control protocol, relayed by the manager (see manager.rs) to its
+//! geph5-client child, which reaches the broker however it can. But that makes
+//! updates impossible exactly when the manager is broken or too old to answer,
+//! which is when an update is needed most.
+//!
+//! So when the engine can't answer we try the mirrors listed in
+//! `update-mirrors.txt`, compiled in, each serving `manifest.json` and the
+//! artifacts under the same `{track}/{version}/{filename}` layout as the engine's
+//! base URL. A build can swap the list out with `GEPH_UPDATE_MIRRORS` (base URLs
+//! separated by commas or whitespace), e.g. to point a test build elsewhere. Mirrors are
+//! fetched through the tunnel's local HTTP proxy when the tunnel is up, since
+//! they may well be blocked otherwise, and directly when it isn't (or the proxy
+//! fails). Artifacts are then downloaded the same way their manifest came.
+//!
+//! Nothing here is trusted more than the engine's answer: every manifest entry
+//! and artifact goes through the same signature and hash checks (signing.rs,
+//! download.rs) whichever source it came from. Checks take their source as a
+//! `ManifestSource`, so the tests (tests.rs) can serve their own.
+
+use std::{future::Future, net::SocketAddr, time::Duration};
+
+use async_trait::async_trait;
+use geph5_misc_rpc::client_control::ControlClient;
+use nanorpc::{JrpcRequest, JrpcResponse, RpcTransport};
+
+use crate::manager::{daemon_rpc, tunnel_http_proxy};
+
+/// The mirror list: `GEPH_UPDATE_MIRRORS` if the build sets it, otherwise the
+/// one kept in-tree.
+const MIRRORS: &str = match option_env!("GEPH_UPDATE_MIRRORS") {
+    Some(mirrors) => mirrors,
+    None => include_str!("update-mirrors.txt"),
+};
+/// How long a mirror gets to send its manifest.
+const MANIFEST_TIMEOUT: Duration = Duration::from_secs(30);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// A fetched (not yet verified) manifest.
+pub struct Manifest {
+    pub manifest: serde_json::Value,
+    /// Artifacts are at `{base_url}/{track}/{version}/{filename}`.
+    pub base_url: String,
+    /// What to download them with.
+    pub client: reqwest::Client,
+}
+
+/// Somewhere update manifests come from.
+#[async_trait]
+pub trait ManifestSource {
+    async fn fetch(&self) -> anyhow::Result<Manifest>;
+}
+
+/// The engine, or failing that the first mirror that answers.
+pub struct EngineOrMirrors;
+
+#[async_trait]
+impl ManifestSource for EngineOrMirrors {
+    async fn fetch(&self) -> anyhow::Result<Manifest> {
+        fetch_from(
+            from_engine().await,
+            &parse_mirrors(MIRRORS),
+            tunnel_http_proxy(),
+        )
+        .await
+    }
+}
+
+/// The engine's answer if it has one, or else the first of `mirrors` that
+/// answers, through the tunnel's proxy (once `proxy` resolves) and then directly.
+pub(super) async fn fetch_from(
+    engine: anyhow::Result<(serde_json::Value, String)>,
+    mirrors: &[&str],
+    proxy: impl Future<Output = Option<SocketAddr>>,
+) -> anyhow::Result<Manifest> {
+    let mut errors = vec![];
+    match engine {
+        Ok((manifest, base_url)) => {
+            return Ok(Manifest {
+                manifest,
+                base_url,
+                client: direct_client()?,
+            });
+        }
+        Err(err) => {
+            tracing::debug!(
+                err = debug(&err),
+                "engine could not fetch the update manifest"
+            );
+            errors.push(format!("engine: {err:#}"));
+        }
+    }
+
+    if mirrors.is_empty() {
+        anyhow::bail!("{}; no update mirrors are configured", errors.join("; "));
+    }
+    let mut routes = vec![];
+    if let Some(proxy) = proxy.await {
+        routes.push(("tunnel proxy", proxied_client(proxy)?));
+    }
+    routes.push(("direct", direct_client()?));
+    for mirror in mirrors {
+        for (route, client) in &routes {
+            match from_mirror(client, mirror).await {
+                Ok(manifest) => {
+                    tracing::debug!(mirror, route, "update manifest fetched from a mirror");
+                    return Ok(Manifest {
+                        manifest,
+                        base_url: mirror.to_string(),
+                        client: client.clone(),
+                    });
+                }
+                Err(err) => errors.push(format!("{mirror} ({route}): {err:#}")),
+            }
+        }
+    }
+    anyhow::bail!(
+        "could not fetch the update manifest from anywhere: {}",
+        errors.join("; ")
+    )
+}
+
+async fn from_engine() -> anyhow::Result<(serde_json::Value, String)> {
+    ControlClient(DaemonRpcTransport)
+        .get_update_manifest()
+        .await?
+        .map_err(|e| anyhow::anyhow!(e))
+}
+
+async fn from_mirror(client: &reqwest::Client, mirror: &str) -> anyhow::Result<serde_json::Value> {
+    let body = client
+        .get(format!("{mirror}/manifest.json"))
+        .timeout(MANIFEST_TIMEOUT)
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+    Ok(serde_json::from_slice(&body)?)
+}
+
+/// Mirror base URLs, without trailing slashes. `#` starts a comment.
+fn parse_mirrors(list: &str) -> Vec<&str> {
+    list.lines()
+        .flat_map(|line| line.split('#').next().unwrap_or("").split([',', ' ', '\t']))
+        .map(|mirror| mirror.trim().trim_end_matches('/'))
+        .filter(|mirror| !mirror.is_empty())
+        .collect()
+}
+
+fn direct_client() -> anyhow::Result<reqwest::Client> {
+    Ok(reqwest::Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .build()?)
+}
+
+fn proxied_client(proxy: SocketAddr) -> anyhow::Result<reqwest::Client> {
+    Ok(reqwest::Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .proxy(reqwest::Proxy::all(format!("http://{proxy}"))?)
+        .build()?)
+}
+
+struct DaemonRpcTransport;
+
+#[async_trait]
+impl RpcTransport for DaemonRpcTransport {
+    type Error = anyhow::Error;
+    async fn call_raw(&self, req: JrpcRequest) -> Result<JrpcResponse, Self::Error> {
+        daemon_rpc(req).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mirror_lists_split_on_commas_and_whitespace() {
+        assert_eq!(
+            parse_mirrors(
+                "# ours\nhttps://a.example/geph/, https://b.example # old\n\thttps://c.example,,"
+            ),
+            [
+                "https://a.example/geph",
+                "https://b.example",
+                "https://c.example"
+            ]
+        );
+        assert!(parse_mirrors("").is_empty());
+    }
+}
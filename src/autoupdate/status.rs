@@ -0,0 +1,169 @@
+//! Where the update lifecycle stands, for the webview.
+//!
+//! Until the frontend could see it, the only sign of an update was the modal at
+//! the next startup. Now every stage is kept here and pushed to the webview as a
+//! `geph_update_status` event, so it can show a small banner while the app runs
+//! and offer the same choices as the modal: install now or at the next safe
+//! moment (deferred.rs), remind me later, or skip this version.
+//!
+//! Those last two have to outlive the process, or the user would be asked again
+//! at the very next launch. They are kept in `update-decisions.json`, next to
+//! `update-metadata.json` in the download cache. Erasing the whole cache (when
+//! the user clears Geph's data on uninstall) forgets them along with the
+//! downloads they were about, which is what we want; clearing just the downloads
+//! (cache.rs) doesn't.
+
+use std::{
+    fs,
+    io::ErrorKind,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{DECISIONS_FILE, InstallWhen, cache_root};
+
+/// How long "remind me later" keeps an update quiet.
+const REMIND_LATER: Duration = Duration::from_secs(24 * 3600);
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum UpdateStatus {
+    /// Nothing to report: up to date, not yet checked, or the user put the
+    /// pending update off.
+    Idle,
+    Checking,
+    Downloading {
+        version: String,
+        /// `None` if the server didn't say how big the file is.
+        percent: Option<u8>,
+    },
+    /// Downloaded, verified, and waiting for the user.
+    Ready {
+        version: String,
+        notes: Option<String>,
+        /// Back to the selected channel's older release, from a beta or nightly
+        /// build (see channel.rs).
+        downgrade: bool,
+    },
+    /// Accepted, to be installed once it won't drop the tunnel (see
+    /// deferred.rs).
+    Deferred {
+        version: String,
+        when: InstallWhen,
+    },
+    Failed {
+        error: String,
+    },
+}
+
+static STATUS: Mutex<UpdateStatus> = Mutex::new(UpdateStatus::Idle);
+
+/// The update lifecycle right now.
+pub fn get() -> UpdateStatus {
+    STATUS.lock().unwrap().clone()
+}
+
+pub(super) fn set(status: UpdateStatus) {
+    *STATUS.lock().unwrap() = status.clone();
+    crate::rpc::push_event("geph_update_status", status);
+}
+
+/// What the user told us about offered updates.
+#[derive(Default, Serialize, Deserialize)]
+pub(super) struct Decisions {
+    /// "Skip this version": never offer this one again.
+    #[serde(default)]
+    skipped_version: Option<String>,
+    /// "Remind me later": offer nothing until then (Unix seconds).
+    #[serde(default)]
+    remind_after: Option<u64>,
+}
+
+impl Decisions {
+    /// The saved decisions. An unreadable file counts as none; the worst that
+    /// does is ask again.
+    pub fn load() -> Self {
+        let Ok(root) = cache_root() else {
+            return Self::default();
+        };
+        match fs::read(root.join(DECISIONS_FILE)) {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_default(),
+            Err(err) => {
+                if err.kind() != ErrorKind::NotFound {
+                    tracing::debug!(err = debug(err), "could not read update decisions");
+                }
+                Self::default()
+            }
+        }
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        fs::write(
+            cache_root()?.join(DECISIONS_FILE),
+            serde_json::to_vec(self)?,
+        )?;
+        Ok(())
+    }
+
+    /// Whether we may offer `version` now.
+    pub fn allows(&self, version: &str) -> bool {
+        self.skipped_version.as_deref() != Some(version)
+            && self.remind_after.is_none_or(|after| now() >= after)
+    }
+
+    pub fn skip(version: &str) -> anyhow::Result<()> {
+        let mut decisions = Self::load();
+        decisions.skipped_version = Some(version.to_string());
+        decisions.save()
+    }
+
+    pub fn remind_later() -> anyhow::Result<()> {
+        let mut decisions = Self::load();
+        decisions.remind_after = Some(now() + REMIND_LATER.as_secs());
+        decisions.save()
+    }
+
+    /// Forget "remind me later", for when the user asks for updates themselves.
+    pub fn clear_reminder() -> anyhow::Result<()> {
+        let mut decisions = Self::load();
+        if decisions.remind_after.take().is_some() {
+            decisions.save()?;
+        }
+        Ok(())
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skipped_and_postponed_updates_are_not_offered() {
+        let skipped = Decisions {
+            skipped_version: Some("5.1.0".into()),
+            remind_after: None,
+        };
+        assert!(!skipped.allows("5.1.0"));
+        assert!(skipped.allows("5.2.0"));
+
+        let postponed = Decisions {
+            skipped_version: None,
+            remind_after: Some(now() + 60),
+        };
+        assert!(!postponed.allows("5.2.0"));
+        let expired = Decisions {
+            skipped_version: None,
+            remind_after: Some(now() - 60),
+        };
+        assert!(expired.allows("5.2.0"));
+    }
+}
@@ -0,0 +1,451 @@
+//! End-to-end checks against a local stand-in for the update server.
+//!
+//! `MockServer` is a tiny_http server on a loopback port that serves a manifest
+//! and artifacts under the same `{track}/{version}/{filename}` layout as the real
+//! thing, honors `Range` requests, and can cut a response short. It's also the
+//! `ManifestSource` of an `Updater` that trusts a test key and caches into a
+//! scratch directory, so nothing here touches the network, the engine, or the
+//! user's own cache.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::Cursor,
+    net::{SocketAddr, TcpListener},
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use ed25519_dalek::{Signer, SigningKey};
+use reqwest::header::{self, HeaderMap, HeaderValue};
+use serde_json::json;
+use tiny_http::{Header, Response, Server};
+
+use super::*;
+
+const FILENAME: &str = "geph.bin";
+
+#[derive(Clone, Default)]
+struct Served {
+    files: BTreeMap<String, Vec<u8>>,
+    /// Paths whose next full response stops halfway through the body.
+    truncate_once: BTreeSet<String>,
+    /// Every request, as (path, `Range` header).
+    requests: Vec<(String, Option<String>)>,
+}
+
+struct MockServer {
+    base_url: String,
+    client: reqwest::Client,
+    served: Arc<Mutex<Served>>,
+}
+
+impl MockServer {
+    fn start() -> Self {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}", server.server_addr().to_ip().unwrap());
+        let served = Arc::new(Mutex::new(Served::default()));
+        let state = served.clone();
+        std::thread::spawn(move || {
+            for request in server.incoming_requests() {
+                let path = request.url().to_string();
+                let range = request
+                    .headers()
+                    .iter()
+                    .find(|h| h.field.equiv("Range"))
+                    .map(|h| h.value.to_string());
+                let mut state = state.lock().unwrap();
+                state.requests.push((path.clone(), range.clone()));
+                let Some(body) = state.files.get(&path).cloned() else {
+                    let _ = request.respond(Response::empty(404));
+                    continue;
+                };
+                let start = range
+                    .as_deref()
+                    .and_then(|range| range.strip_prefix("bytes=")?.strip_suffix('-'))
+                    .and_then(|start| start.parse::<usize>().ok())
+                    .filter(|start| *start < body.len());
+                let _ = match start {
+                    Some(start) => {
+                        let range = format!("bytes {start}-{}/{}", body.len() - 1, body.len());
+                        request.respond(
+                            Response::from_data(&body[start..])
+                                .with_status_code(206)
+                                .with_header(Header::from_bytes("Content-Range", range).unwrap()),
+                        )
+                    }
+                    // Promises the whole body and then hangs up, as a dropped
+                    // connection would.
+                    None if state.truncate_once.remove(&path) => request.respond(Response::new(
+                        200.into(),
+                        vec![],
+                        Cursor::new(body[..body.len() / 2].to_vec()),
+                        Some(body.len()),
+                        None,
+                    )),
+                    None => request.respond(Response::from_data(body)),
+                };
+            }
+        });
+        // One request per connection, so a cut-short response ends in EOF
+        // rather than a client waiting on a kept-alive socket.
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONNECTION, HeaderValue::from_static("close"));
+        let client = reqwest::Client::builder()
+            .default_headers(headers)
+            .build()
+            .unwrap();
+        MockServer {
+            base_url,
+            client,
+            served,
+        }
+    }
+
+    fn serve(&self, path: &str, body: impl Into<Vec<u8>>) {
+        let mut served = self.served.lock().unwrap();
+        served.files.insert(path.to_string(), body.into());
+    }
+
+    fn requests(&self) -> Vec<(String, Option<String>)> {
+        self.served.lock().unwrap().requests.clone()
+    }
+
+    fn requested(&self, suffix: &str) -> bool {
+        self.requests()
+            .iter()
+            .any(|(path, _)| path.ends_with(suffix))
+    }
+}
+
+#[async_trait]
+impl ManifestSource for MockServer {
+    async fn fetch(&self) -> anyhow::Result<source::Manifest> {
+        let body = self
+            .client
+            .get(format!("{}/manifest.json", self.base_url))
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        Ok(source::Manifest {
+            manifest: serde_json::from_slice(&body)?,
+            base_url: self.base_url.clone(),
+            client: self.client.clone(),
+        })
+    }
+}
+
+fn signing_key() -> SigningKey {
+    SigningKey::from_bytes(&[7; 32])
+}
+
+fn sign(message: String) -> String {
+    hex::encode(signing_key().sign(message.as_bytes()).to_bytes())
+}
+
+fn sha256(bytes: &[u8]) -> String {
+    hex::encode(hmac_sha256::Hash::hash(bytes))
+}
+
+/// A release as the server would publish it. Tests break it before `publish`.
+struct Release {
+    version: &'static str,
+    artifact: Vec<u8>,
+    /// What the manifest claims (and the signatures cover).
+    sha256: String,
+    entry_signature: String,
+}
+
+impl Release {
+    fn new(version: &'static str) -> Self {
+        let artifact = format!("installer for {version}\n")
+            .repeat(300)
+            .into_bytes();
+        let sha256 = sha256(&artifact);
+        let track = channel::track(UpdateChannel::Stable);
+        let entry_signature = sign(format!(
+            "geph-update-v1\n{track}\n{version}\n{FILENAME}\n{sha256}"
+        ));
+        Release {
+            version,
+            artifact,
+            sha256,
+            entry_signature,
+        }
+    }
+
+    fn publish(&self, server: &MockServer) {
+        let track = channel::track(UpdateChannel::Stable);
+        let manifest = json!({
+            track.clone(): {
+                "version": self.version,
+                "sha256": self.sha256,
+                "filename": FILENAME,
+                "signatures": { "test": self.entry_signature },
+                "notes": "Faster connections.",
+            }
+        });
+        server.serve("/manifest.json", manifest.to_string());
+        let path = format!("/{track}/{}/{FILENAME}", self.version);
+        server.serve(&path, self.artifact.clone());
+        let sig = format!(
+            "test {}\n",
+            sign(format!("geph-update-artifact-v1\n{}", self.sha256))
+        );
+        server.serve(&format!("{path}.sig"), sig);
+    }
+}
+
+/// The real fallback path (source.rs) with the engine down and the mock server
+/// as the only mirror, behind a tunnel proxy that doesn't answer.
+struct EngineDown {
+    mirror: String,
+    dead_proxy: SocketAddr,
+}
+
+#[async_trait]
+impl ManifestSource for EngineDown {
+    async fn fetch(&self) -> anyhow::Result<source::Manifest> {
+        source::fetch_from(
+            Err(anyhow::anyhow!("the manager is not running")),
+            &[self.mirror.as_str()],
+            async { Some(self.dead_proxy) },
+        )
+        .await
+    }
+}
+
+/// An updater for `current` on the stable channel, trusting only the test key
+/// and caching into a fresh directory named after the test.
+fn updater<S>(source: S, current: &str, name: &str) -> Updater<S> {
+    let root = std::env::temp_dir().join(format!("geph-update-test-{}-{name}", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(&root).unwrap();
+    let keys = KeyRing::parse(&format!(
+        "test {}",
+        hex::encode(signing_key().verifying_key().as_bytes())
+    ))
+    .unwrap();
+    Updater {
+        source,
+        keys,
+        root,
+        current: Version::parse(current).unwrap(),
+        channel: UpdateChannel::Stable,
+    }
+}
+
+fn remove_scratch<S>(updater: &Updater<S>) {
+    fs::remove_dir_all(&updater.root).unwrap();
+}
+
+/// Nothing left behind by a failed check: no metadata, and no file or partial
+/// download in the release's slot.
+fn assert_nothing_cached(updater: &Updater<MockServer>, release: &Release) {
+    assert!(updater.load_metadata().unwrap().is_none());
+    let slot = updater.root.join(&release.sha256);
+    assert!(!slot.join(FILENAME).exists());
+    assert!(!slot.join(format!("{FILENAME}.part")).exists());
+}
+
+#[test]
+fn caches_a_signed_upgrade() {
+    let server = MockServer::start();
+    let release = Release::new("5.2.0");
+    release.publish(&server);
+    let updater = updater(server, "5.1.0", "upgrade");
+
+    let result = geph5_rt::block_on(updater.ensure_cached()).unwrap();
+    assert_eq!(result, CacheResult::CachedFresh);
+    let metadata = updater.load_metadata().unwrap().unwrap();
+    assert_eq!(metadata.version, "5.2.0");
+    assert_eq!(metadata.sha256, release.sha256);
+    assert_eq!(metadata.track, channel::track(UpdateChannel::Stable));
+    assert_eq!(metadata.notes.as_deref(), Some("Faster connections."));
+    assert_eq!(fs::read(&metadata.download_path).unwrap(), release.artifact);
+    assert_eq!(updater.offer(&metadata).unwrap(), Some(Offer::Upgrade));
+    geph5_rt::block_on(updater.verify_cached(&metadata)).unwrap();
+
+    // Checking again finds it cached and downloads nothing.
+    let downloads = |updater: &Updater<MockServer>| {
+        updater
+            .source
+            .requests()
+            .iter()
+            .filter(|(path, _)| path.ends_with(FILENAME))
+            .count()
+    };
+    assert_eq!(downloads(&updater), 1);
+    let result = geph5_rt::block_on(updater.ensure_cached()).unwrap();
+    assert_eq!(result, CacheResult::AlreadyCached);
+    assert_eq!(downloads(&updater), 1);
+    remove_scratch(&updater);
+}
+
+#[test]
+fn falls_back_to_a_mirror_when_the_engine_fails() {
+    let server = MockServer::start();
+    let release = Release::new("5.2.0");
+    release.publish(&server);
+    // A port nothing listens on any more.
+    let dead_proxy = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let source = EngineDown {
+        mirror: server.base_url.clone(),
+        dead_proxy,
+    };
+    let updater = updater(source, "5.1.0", "mirror");
+
+    let result = geph5_rt::block_on(updater.ensure_cached()).unwrap();
+    assert_eq!(result, CacheResult::CachedFresh);
+    let metadata = updater.load_metadata().unwrap().unwrap();
+    assert_eq!(metadata.version, "5.2.0");
+    assert_eq!(fs::read(&metadata.download_path).unwrap(), release.artifact);
+    assert!(server.requested("/manifest.json"));
+    assert!(server.requested(FILENAME));
+    remove_scratch(&updater);
+}
+
+#[test]
+fn no_newer_release_clears_stale_metadata() {
+    let server = MockServer::start();
+    Release::new("5.2.0").publish(&server);
+    let mut updater = updater(server, "5.1.0", "stale");
+    geph5_rt::block_on(updater.ensure_cached()).unwrap();
+    assert!(updater.load_metadata().unwrap().is_some());
+
+    // The update got installed: the cached one is no longer an offer, and the
+    // next check forgets it.
+    updater.current = Version::parse("5.2.0+build.2").unwrap();
+    let metadata = updater.load_metadata().unwrap().unwrap();
+    assert_eq!(updater.offer(&metadata).unwrap(), None);
+    let result = geph5_rt::block_on(updater.ensure_cached()).unwrap();
+    assert_eq!(result, CacheResult::AlreadyCurrent);
+    assert!(updater.load_metadata().unwrap().is_none());
+    remove_scratch(&updater);
+}
+
+#[test]
+fn older_releases_are_not_installed() {
+    let server = MockServer::start();
+    let release = Release::new("5.1.4");
+    release.publish(&server);
+    let mut updater = updater(server, "5.1.5", "downgrade");
+
+    // A validly signed but older stable release, replayed to a stable build.
+    let result = geph5_rt::block_on(updater.ensure_cached()).unwrap();
+    assert_eq!(result, CacheResult::AlreadyCurrent);
+    assert!(!updater.source.requested(FILENAME));
+    assert_nothing_cached(&updater, &release);
+
+    // Only a build from a less stable channel goes back to it.
+    updater.current = Version::parse("5.2.0-beta.3").unwrap();
+    let result = geph5_rt::block_on(updater.ensure_cached()).unwrap();
+    assert_eq!(result, CacheResult::CachedFresh);
+    let metadata = updater.load_metadata().unwrap().unwrap();
+    assert_eq!(updater.offer(&metadata).unwrap(), Some(Offer::Downgrade));
+    remove_scratch(&updater);
+}
+
+#[test]
+fn an_altered_manifest_hash_is_refused_before_downloading() {
+    let server = MockServer::start();
+    let mut release = Release::new("5.2.0");
+    release.sha256 = sha256(b"something else entirely");
+    release.publish(&server);
+    let updater = updater(server, "5.1.0", "wrong-hash");
+    // Re-signing the artifact under the new hash isn't enough: the entry
+    // signature covers the hash the release was signed with.
+    let err = geph5_rt::block_on(updater.ensure_cached()).unwrap_err();
+    assert!(format!("{err:#}").contains("not signed by a trusted key"));
+    assert!(!updater.source.requested(FILENAME));
+    assert_nothing_cached(&updater, &release);
+    remove_scratch(&updater);
+}
+
+#[test]
+fn a_corrupt_download_is_discarded() {
+    let server = MockServer::start();
+    let mut release = Release::new("5.2.0");
+    release.publish(&server);
+    release.artifact[100] ^= 0xff;
+    let path = format!(
+        "/{}/5.2.0/{FILENAME}",
+        channel::track(UpdateChannel::Stable)
+    );
+    server.serve(&path, release.artifact.clone());
+    let updater = updater(server, "5.1.0", "corrupt");
+
+    let err = geph5_rt::block_on(updater.ensure_cached()).unwrap_err();
+    assert!(format!("{err:#}").contains("hash mismatch"));
+    assert_nothing_cached(&updater, &release);
+    remove_scratch(&updater);
+}
+
+#[test]
+fn a_truncated_transfer_resumes() {
+    let server = MockServer::start();
+    let release = Release::new("5.2.0");
+    release.publish(&server);
+    let path = format!(
+        "/{}/5.2.0/{FILENAME}",
+        channel::track(UpdateChannel::Stable)
+    );
+    server
+        .served
+        .lock()
+        .unwrap()
+        .truncate_once
+        .insert(path.clone());
+    let updater = updater(server, "5.1.0", "truncated");
+
+    let result = geph5_rt::block_on(updater.ensure_cached()).unwrap();
+    assert_eq!(result, CacheResult::CachedFresh);
+    let metadata = updater.load_metadata().unwrap().unwrap();
+    assert_eq!(fs::read(&metadata.download_path).unwrap(), release.artifact);
+    let half = release.artifact.len() / 2;
+    let attempts: Vec<_> = updater
+        .source
+        .requests()
+        .into_iter()
+        .filter(|(requested, _)| *requested == path)
+        .map(|(_, range)| range)
+        .collect();
+    assert_eq!(attempts, [None, Some(format!("bytes={half}-"))]);
+    remove_scratch(&updater);
+}
+
+#[test]
+fn metadata_round_trips_and_is_checked_before_use() {
+    let server = MockServer::start();
+    Release::new("5.2.0").publish(&server);
+    let updater = updater(server, "5.1.0", "metadata");
+    assert!(updater.load_metadata().unwrap().is_none());
+    updater.clear_metadata().unwrap();
+
+    geph5_rt::block_on(updater.ensure_cached()).unwrap();
+    let metadata = updater.load_metadata().unwrap().unwrap();
+    updater.clear_metadata().unwrap();
+    assert!(updater.load_metadata().unwrap().is_none());
+    updater.write_metadata(&metadata).unwrap();
+    assert_eq!(updater.pending().unwrap().version, "5.2.0");
+
+    // Cached from another channel's track: not an offer on this one.
+    let other = UpdateMetadata {
+        track: channel::track(UpdateChannel::Beta),
+        ..updater.load_metadata().unwrap().unwrap()
+    };
+    assert_eq!(updater.offer(&other).unwrap(), None);
+
+    // Changed on disk after it was cached.
+    fs::write(&metadata.download_path, b"not the installer").unwrap();
+    let err = geph5_rt::block_on(updater.verify_cached(&metadata)).unwrap_err();
+    assert!(format!("{err:#}").contains("hash mismatch"));
+
+    fs::write(updater.metadata_path(), b"{ not json").unwrap();
+    assert!(updater.load_metadata().is_err());
+    remove_scratch(&updater);
+}
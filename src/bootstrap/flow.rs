@@ -0,0 +1,702 @@
+//! The bootstrap's decision logic, as a state machine over its side effects.
+//!
+//! Everything that touches the system (probing the manager, elevating, showing
+//! dialogs, relaunching the Flatpak) goes through the four traits below, which
+//! mod.rs implements for real and the tests implement with scripted fakes. That
+//! keeps every branch (stale Flatpak, fresh install needing a relaunch, the
+//! retry loop, the Windows grace period, the relaunch-dialog fallback) testable
+//! without a manager, root, or a display.
+
+use std::time::Duration;
+
+use crate::diagnose::Cause;
+use crate::splash::{self, Step};
+
+/// How often we poll a manager we expect to come up.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// Windows: polls to allow the boot-triggered manager task to start before we
+/// bother the user (~5s).
+const GRACE_POLLS: u32 = 20;
+/// Polls to wait for a freshly (re)started manager to answer (~10s).
+const START_POLLS: u32 = 40;
+
+/// What the bootstrap can find out about the manager.
+pub trait Probe {
+    /// Does the control endpoint answer right now?
+    fn reachable(&self) -> bool;
+    /// Is the answering manager the wrong build (Flatpak: not the one we bundle;
+    /// elsewhere: a protocol revision we don't speak)?
+    fn outdated(&self) -> bool;
+    /// Why the manager isn't usable; see diagnose.rs.
+    fn diagnose(&self) -> Cause;
+    fn sleep(&self, duration: Duration);
+}
+
+/// Runs the privileged install, elevating once.
+pub trait Elevator {
+    fn install(&self) -> anyhow::Result<()>;
+}
+
+/// The native dialogs the flow can show.
+pub trait Dialogs {
+    /// Explain the setup and what's wrong; `true` to go ahead.
+    fn explain(&self, cause: &Cause) -> bool;
+    /// Report a failed install; `true` to retry.
+    fn retry(&self, err: &str) -> bool;
+    /// Ask the user to reopen Geph themselves.
+    fn reopen(&self);
+}
+
+/// Hands off to a fresh Flatpak sandbox, one that can see `/run/geph`.
+pub trait Relauncher {
+    /// Schedule a new instance to start once we exit; `false` if that can't be
+    /// arranged.
+    fn relaunch(&self) -> bool;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Platform {
+    Native,
+    Flatpak,
+    Windows,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum State {
+    /// Is the manager there and current?
+    Check,
+    /// Windows: not answering yet, maybe still starting at boot.
+    Grace {
+        polls_left: u32,
+    },
+    /// Something needs doing; find out what.
+    Diagnose {
+        was_reachable: bool,
+    },
+    /// Explain and ask permission.
+    Consent {
+        cause: Cause,
+        was_reachable: bool,
+    },
+    /// Elevate and install.
+    Install {
+        was_reachable: bool,
+    },
+    /// The install failed; offer a retry.
+    Failed {
+        error: String,
+        was_reachable: bool,
+    },
+    /// Wait for the (re)started manager to answer.
+    WaitStarted,
+    /// This sandbox can't see the manager; hand off to a fresh one.
+    Relaunch,
+    Done(Outcome),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// The manager answers and is current.
+    Ready,
+    /// Carry on without a working manager; the GUI surfaces its own error.
+    Unavailable,
+    /// The user declined the setup.
+    Declined,
+    /// This instance must exit: a fresh one is scheduled, or the user was asked
+    /// to reopen Geph.
+    Relaunch,
+}
+
+pub struct Flow<'a> {
+    pub platform: Platform,
+    /// Administrator policy allows setup without the explanation dialog.
+    pub unattended: bool,
+    pub probe: &'a dyn Probe,
+    pub elevator: &'a dyn Elevator,
+    pub dialogs: &'a dyn Dialogs,
+    pub relauncher: &'a dyn Relauncher,
+}
+
+impl Flow<'_> {
+    /// Startup: make sure the manager is installed, current, and answering.
+    pub fn ensure(&self) -> Outcome {
+        self.run(State::Check, |_| {})
+    }
+
+    /// Runtime repair of a manager that went away. The GUI only gets this far
+    /// once the manager answered, so on Flatpak `/run/geph` is already mounted.
+    pub fn repair(&self) -> Outcome {
+        self.run(
+            State::Diagnose {
+                was_reachable: true,
+            },
+            |_| {},
+        )
+    }
+
+    /// Step from `state` until done, showing each state to `trace`.
+    pub fn run(&self, mut state: State, mut trace: impl FnMut(&State)) -> Outcome {
+        loop {
+            trace(&state);
+            if let State::Done(outcome) = state {
+                return outcome;
+            }
+            state = self.step(state);
+        }
+    }
+
+    fn step(&self, state: State) -> State {
+        match state {
+            State::Check => {
+                splash::set_step(Step::CheckingService);
+                if !self.probe.reachable() {
+                    if self.platform == Platform::Windows {
+                        State::Grace {
+                            polls_left: GRACE_POLLS,
+                        }
+                    } else {
+                        State::Diagnose {
+                            was_reachable: false,
+                        }
+                    }
+                } else if self.probe.outdated() {
+                    State::Diagnose {
+                        was_reachable: true,
+                    }
+                } else {
+                    State::Done(Outcome::Ready)
+                }
+            }
+            State::Grace { polls_left: 0 } => State::Diagnose {
+                was_reachable: false,
+            },
+            State::Grace { polls_left } => {
+                self.probe.sleep(POLL_INTERVAL);
+                if !self.probe.reachable() {
+                    State::Grace {
+                        polls_left: polls_left - 1,
+                    }
+                } else if self.probe.outdated() {
+                    State::Diagnose {
+                        was_reachable: true,
+                    }
+                } else {
+                    State::Done(Outcome::Ready)
+                }
+            }
+            State::Diagnose { was_reachable } => match self.probe.diagnose() {
+                // Nothing to install: the manager is fine, this sandbox just
+                // predates it.
+                Cause::FlatpakMountMissing => State::Relaunch,
+                cause => State::Consent {
+                    cause,
+                    was_reachable,
+                },
+            },
+            State::Consent {
+                cause,
+                was_reachable,
+            } => {
+                if self.unattended || self.dialogs.explain(&cause) {
+                    State::Install { was_reachable }
+                } else {
+                    State::Done(Outcome::Declined)
+                }
+            }
+            State::Install { was_reachable } => match self.elevator.install() {
+                // Fresh Flatpak install: `/run/geph` did not exist when this
+                // sandbox started, so the bind-mount missed it.
+                Ok(()) if self.platform == Platform::Flatpak && !was_reachable => State::Relaunch,
+                Ok(()) => State::WaitStarted,
+                Err(err) => State::Failed {
+                    error: format!("{err:#}"),
+                    was_reachable,
+                },
+            },
+            State::Failed {
+                error,
+                was_reachable,
+            } => {
+                if self.dialogs.retry(&error) {
+                    State::Install { was_reachable }
+                } else {
+                    State::Done(Outcome::Declined)
+                }
+            }
+            State::WaitStarted => {
+                splash::set_step(Step::StartingService);
+                for _ in 0..START_POLLS {
+                    if self.probe.reachable() {
+                        return State::Done(Outcome::Ready);
+                    }
+                    self.probe.sleep(POLL_INTERVAL);
+                }
+                if self.platform == Platform::Flatpak {
+                    // Only a fresh sandbox might see it.
+                    State::Relaunch
+                } else {
+                    State::Done(Outcome::Unavailable)
+                }
+            }
+            State::Relaunch => {
+                if !self.relauncher.relaunch() {
+                    self.dialogs.reopen();
+                }
+                State::Done(Outcome::Relaunch)
+            }
+            State::Done(outcome) => State::Done(outcome),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::{Cell, RefCell},
+        collections::VecDeque,
+    };
+
+    use super::*;
+
+    /// Answers `reachable` from a script, repeating the last answer once it
+    /// runs out.
+    struct FakeProbe {
+        reachable: RefCell<VecDeque<bool>>,
+        outdated: bool,
+        cause: Cause,
+        sleeps: Cell<u32>,
+    }
+
+    impl FakeProbe {
+        fn new(reachable: &[bool]) -> Self {
+            Self {
+                reachable: RefCell::new(reachable.iter().copied().collect()),
+                outdated: false,
+                cause: Cause::ServiceMissing,
+                sleeps: Cell::new(0),
+            }
+        }
+    }
+
+    impl Probe for FakeProbe {
+        fn reachable(&self) -> bool {
+            let mut script = self.reachable.borrow_mut();
+            if script.len() > 1 {
+                script.pop_front().unwrap()
+            } else {
+                script[0]
+            }
+        }
+        fn outdated(&self) -> bool {
+            self.outdated
+        }
+        fn diagnose(&self) -> Cause {
+            self.cause.clone()
+        }
+        fn sleep(&self, _: Duration) {
+            self.sleeps.set(self.sleeps.get() + 1);
+        }
+    }
+
+    /// Fails as many times as scripted, then succeeds.
+    #[derive(Default)]
+    struct FakeElevator {
+        failures: Cell<u32>,
+        calls: Cell<u32>,
+    }
+
+    impl Elevator for FakeElevator {
+        fn install(&self) -> anyhow::Result<()> {
+            self.calls.set(self.calls.get() + 1);
+            if self.failures.get() > 0 {
+                self.failures.set(self.failures.get() - 1);
+                anyhow::bail!("pkexec exited with code 126");
+            }
+            Ok(())
+        }
+    }
+
+    struct FakeDialogs {
+        consent: bool,
+        retries: Cell<u32>,
+        explained: Cell<u32>,
+        errors: RefCell<Vec<String>>,
+        reopened: Cell<bool>,
+    }
+
+    impl FakeDialogs {
+        fn new(consent: bool, retries: u32) -> Self {
+            Self {
+                consent,
+                retries: Cell::new(retries),
+                explained: Cell::new(0),
+                errors: RefCell::new(vec![]),
+                reopened: Cell::new(false),
+            }
+        }
+    }
+
+    impl Dialogs for FakeDialogs {
+        fn explain(&self, _: &Cause) -> bool {
+            self.explained.set(self.explained.get() + 1);
+            self.consent
+        }
+        fn retry(&self, err: &str) -> bool {
+            self.errors.borrow_mut().push(err.to_string());
+            let retries = self.retries.get();
+            self.retries.set(retries.saturating_sub(1));
+            retries > 0
+        }
+        fn reopen(&self) {
+            self.reopened.set(true);
+        }
+    }
+
+    struct FakeRelauncher {
+        works: bool,
+        called: Cell<bool>,
+    }
+
+    impl Relauncher for FakeRelauncher {
+        fn relaunch(&self) -> bool {
+            self.called.set(true);
+            self.works
+        }
+    }
+
+    struct Harness {
+        platform: Platform,
+        unattended: bool,
+        probe: FakeProbe,
+        elevator: FakeElevator,
+        dialogs: FakeDialogs,
+        relauncher: FakeRelauncher,
+    }
+
+    impl Harness {
+        fn new(platform: Platform, reachable: &[bool]) -> Self {
+            Self {
+                platform,
+                unattended: false,
+                probe: FakeProbe::new(reachable),
+                elevator: FakeElevator::default(),
+                dialogs: FakeDialogs::new(true, 0),
+                relauncher: FakeRelauncher {
+                    works: true,
+                    called: Cell::new(false),
+                },
+            }
+        }
+
+        fn flow(&self) -> Flow<'_> {
+            Flow {
+                platform: self.platform,
+                unattended: self.unattended,
+                probe: &self.probe,
+                elevator: &self.elevator,
+                dialogs: &self.dialogs,
+                relauncher: &self.relauncher,
+            }
+        }
+
+        /// Run from `start`, returning the outcome and the states visited (minus
+        /// repeated polling states).
+        fn run(&self, start: State) -> (Outcome, Vec<State>) {
+            let mut states: Vec<State> = vec![];
+            let outcome = self.flow().run(start, |state| {
+                if !matches!(state, State::Grace { .. })
+                    || !matches!(states.last(), Some(State::Grace { .. }))
+                {
+                    states.push(state.clone());
+                }
+            });
+            (outcome, states)
+        }
+    }
+
+    fn diagnose(was_reachable: bool) -> State {
+        State::Diagnose { was_reachable }
+    }
+
+    fn consent(was_reachable: bool) -> State {
+        State::Consent {
+            cause: Cause::ServiceMissing,
+            was_reachable,
+        }
+    }
+
+    fn install(was_reachable: bool) -> State {
+        State::Install { was_reachable }
+    }
+
+    #[test]
+    fn reachable_and_current_needs_nothing() {
+        for platform in [Platform::Native, Platform::Flatpak, Platform::Windows] {
+            let h = Harness::new(platform, &[true]);
+            let (outcome, states) = h.run(State::Check);
+            assert_eq!(outcome, Outcome::Ready);
+            assert_eq!(states, [State::Check, State::Done(Outcome::Ready)]);
+            assert_eq!(h.dialogs.explained.get(), 0);
+            assert_eq!(h.elevator.calls.get(), 0);
+        }
+    }
+
+    #[test]
+    fn native_fresh_install_waits_for_the_service() {
+        // Unreachable at the check, then through two polls after the install.
+        let h = Harness::new(Platform::Native, &[false, false, false, true]);
+        let (outcome, states) = h.run(State::Check);
+        assert_eq!(outcome, Outcome::Ready);
+        assert_eq!(
+            states,
+            [
+                State::Check,
+                diagnose(false),
+                consent(false),
+                install(false),
+                State::WaitStarted,
+                State::Done(Outcome::Ready),
+            ]
+        );
+        assert_eq!(h.elevator.calls.get(), 1);
+        assert_eq!(h.probe.sleeps.get(), 2);
+        assert!(!h.relauncher.called.get());
+    }
+
+    #[test]
+    fn native_service_that_never_starts_is_unavailable_not_fatal() {
+        let h = Harness::new(Platform::Native, &[false]);
+        let (outcome, _) = h.run(State::Check);
+        assert_eq!(outcome, Outcome::Unavailable);
+        assert_eq!(h.probe.sleeps.get(), START_POLLS);
+        assert!(!h.relauncher.called.get());
+    }
+
+    #[test]
+    fn native_protocol_mismatch_reinstalls() {
+        let mut h = Harness::new(Platform::Native, &[true]);
+        h.probe.outdated = true;
+        let (outcome, states) = h.run(State::Check);
+        assert_eq!(outcome, Outcome::Ready);
+        assert_eq!(states[1], diagnose(true));
+        assert_eq!(h.elevator.calls.get(), 1);
+    }
+
+    #[test]
+    fn declining_the_explanation_installs_nothing() {
+        let mut h = Harness::new(Platform::Native, &[false]);
+        h.dialogs = FakeDialogs::new(false, 0);
+        let (outcome, states) = h.run(State::Check);
+        assert_eq!(outcome, Outcome::Declined);
+        assert_eq!(states.last(), Some(&State::Done(Outcome::Declined)));
+        assert_eq!(h.elevator.calls.get(), 0);
+    }
+
+    #[test]
+    fn unattended_policy_skips_the_explanation() {
+        let mut h = Harness::new(Platform::Native, &[false, true]);
+        h.unattended = true;
+        h.dialogs = FakeDialogs::new(false, 0);
+        assert_eq!(h.run(State::Check).0, Outcome::Ready);
+        assert_eq!(h.dialogs.explained.get(), 0);
+        assert_eq!(h.elevator.calls.get(), 1);
+    }
+
+    #[test]
+    fn failed_install_retries_until_it_works() {
+        let mut h = Harness::new(Platform::Native, &[false, true]);
+        h.elevator.failures.set(2);
+        h.dialogs = FakeDialogs::new(true, 2);
+        let (outcome, states) = h.run(State::Check);
+        assert_eq!(outcome, Outcome::Ready);
+        assert_eq!(h.elevator.calls.get(), 3);
+        assert_eq!(
+            *h.dialogs.errors.borrow(),
+            ["pkexec exited with code 126", "pkexec exited with code 126"]
+        );
+        let failed = State::Failed {
+            error: "pkexec exited with code 126".into(),
+            was_reachable: false,
+        };
+        assert_eq!(
+            &states[3..8],
+            [
+                install(false),
+                failed.clone(),
+                install(false),
+                failed,
+                install(false)
+            ]
+        );
+    }
+
+    #[test]
+    fn quitting_at_the_retry_dialog_declines() {
+        let h = Harness::new(Platform::Native, &[false]);
+        h.elevator.failures.set(1);
+        let (outcome, _) = h.run(State::Check);
+        assert_eq!(outcome, Outcome::Declined);
+        assert_eq!(h.elevator.calls.get(), 1);
+        assert_eq!(h.dialogs.errors.borrow().len(), 1);
+    }
+
+    #[test]
+    fn flatpak_fresh_install_relaunches_without_waiting() {
+        let h = Harness::new(Platform::Flatpak, &[false]);
+        let (outcome, states) = h.run(State::Check);
+        assert_eq!(outcome, Outcome::Relaunch);
+        assert_eq!(
+            states,
+            [
+                State::Check,
+                diagnose(false),
+                consent(false),
+                install(false),
+                State::Relaunch,
+                State::Done(Outcome::Relaunch),
+            ]
+        );
+        assert!(h.relauncher.called.get());
+        assert!(!h.dialogs.reopened.get());
+        assert_eq!(h.probe.sleeps.get(), 0);
+    }
+
+    #[test]
+    fn failed_auto_relaunch_falls_back_to_the_reopen_dialog() {
+        let mut h = Harness::new(Platform::Flatpak, &[false]);
+        h.relauncher.works = false;
+        assert_eq!(h.run(State::Check).0, Outcome::Relaunch);
+        assert!(h.relauncher.called.get());
+        assert!(h.dialogs.reopened.get());
+    }
+
+    #[test]
+    fn flatpak_stale_manager_upgrades_in_place() {
+        // Reachable (so `/run/geph` is mounted) but a different build: after the
+        // upgrade the manager comes back in this same sandbox.
+        let mut h = Harness::new(Platform::Flatpak, &[true, false, true]);
+        h.probe.outdated = true;
+        let (outcome, states) = h.run(State::Check);
+        assert_eq!(outcome, Outcome::Ready);
+        assert_eq!(
+            states,
+            [
+                State::Check,
+                diagnose(true),
+                consent(true),
+                install(true),
+                State::WaitStarted,
+                State::Done(Outcome::Ready),
+            ]
+        );
+        assert!(!h.relauncher.called.get());
+    }
+
+    #[test]
+    fn flatpak_upgrade_that_never_answers_relaunches() {
+        let mut h = Harness::new(Platform::Flatpak, &[true, false]);
+        h.probe.outdated = true;
+        let (outcome, states) = h.run(State::Check);
+        assert_eq!(outcome, Outcome::Relaunch);
+        assert_eq!(states[states.len() - 2], State::Relaunch);
+        assert_eq!(h.probe.sleeps.get(), START_POLLS);
+    }
+
+    #[test]
+    fn flatpak_missing_mount_relaunches_without_installing() {
+        let mut h = Harness::new(Platform::Flatpak, &[false]);
+        h.probe.cause = Cause::FlatpakMountMissing;
+        let (outcome, states) = h.run(State::Check);
+        assert_eq!(outcome, Outcome::Relaunch);
+        assert_eq!(
+            states,
+            [
+                State::Check,
+                diagnose(false),
+                State::Relaunch,
+                State::Done(Outcome::Relaunch),
+            ]
+        );
+        assert_eq!(h.dialogs.explained.get(), 0);
+        assert_eq!(h.elevator.calls.get(), 0);
+    }
+
+    #[test]
+    fn windows_grace_period_rides_out_a_slow_boot() {
+        // Down at the check and for four polls, then up: no dialogs at all.
+        let h = Harness::new(
+            Platform::Windows,
+            &[false, false, false, false, false, true],
+        );
+        let (outcome, states) = h.run(State::Check);
+        assert_eq!(outcome, Outcome::Ready);
+        assert_eq!(
+            states,
+            [
+                State::Check,
+                State::Grace {
+                    polls_left: GRACE_POLLS
+                },
+                State::Done(Outcome::Ready),
+            ]
+        );
+        assert_eq!(h.probe.sleeps.get(), 5);
+        assert_eq!(h.dialogs.explained.get(), 0);
+    }
+
+    #[test]
+    fn windows_repairs_after_the_grace_period() {
+        let mut reachable = vec![false; 1 + GRACE_POLLS as usize];
+        reachable.push(true);
+        let h = Harness::new(Platform::Windows, &reachable);
+        let (outcome, states) = h.run(State::Check);
+        assert_eq!(outcome, Outcome::Ready);
+        assert_eq!(
+            &states[2..],
+            [
+                diagnose(false),
+                consent(false),
+                install(false),
+                State::WaitStarted,
+                State::Done(Outcome::Ready),
+            ]
+        );
+        assert_eq!(h.probe.sleeps.get(), GRACE_POLLS);
+    }
+
+    #[test]
+    fn windows_protocol_mismatch_reinstalls_without_the_grace_period() {
+        let mut h = Harness::new(Platform::Windows, &[true]);
+        h.probe.outdated = true;
+        let (outcome, states) = h.run(State::Check);
+        assert_eq!(outcome, Outcome::Ready);
+        assert_eq!(states[1], diagnose(true));
+        assert_eq!(h.elevator.calls.get(), 1);
+    }
+
+    #[test]
+    fn windows_service_that_never_starts_is_unavailable() {
+        let h = Harness::new(Platform::Windows, &[false]);
+        assert_eq!(h.run(State::Check).0, Outcome::Unavailable);
+        assert!(!h.relauncher.called.get());
+    }
+
+    #[test]
+    fn flatpak_repair_waits_in_place_before_relaunching() {
+        let h = Harness::new(Platform::Flatpak, &[false, true]);
+        assert_eq!(h.flow().repair(), Outcome::Ready);
+        assert!(!h.relauncher.called.get());
+
+        let h = Harness::new(Platform::Flatpak, &[false]);
+        assert_eq!(h.flow().repair(), Outcome::Relaunch);
+        assert!(h.relauncher.called.get());
+    }
+
+    #[test]
+    fn repair_declined_leaves_the_app_running() {
+        let mut h = Harness::new(Platform::Windows, &[false]);
+        h.dialogs = FakeDialogs::new(false, 0);
+        assert_eq!(h.flow().repair(), Outcome::Declined);
+        assert!(!h.relauncher.called.get());
+    }
+}
@@ -0,0 +1,893 @@
+//! Startup bootstrap of the privileged `geph manager` (Linux + Windows).
+//!
+//! The GUI is an unprivileged client of a persistent root manager (see manager.rs):
+//! the manager owns the TUN device, routing, kill-switch, and the control endpoint
+//! (`/run/geph/control.sock` on Linux, a named pipe on Windows). A sandboxed Flatpak
+//! GUI cannot run that manager itself, and even a native GUI shouldn't assume it's
+//! already installed. So, run early in `main()`, this module makes sure a host
+//! manager is present, current, and answering before the webview tries to talk to it.
+//!
+//! Linux logic:
+//!   * If the control socket answers — and, on Flatpak, the installed manager binary
+//!     matches the one we bundle — there's nothing to do.
+//!   * Otherwise we explain via a native dialog (see dialog.rs), elevate once (pkexec,
+//!     run0, or a terminal-hosted sudo, whichever can prompt here; see elevate.rs),
+//!     and run the privileged installer:
+//!       - Native: `geph5 register-manager` (the `.deb` put `geph5` on PATH).
+//!       - Flatpak: stage the bundled static `geph5`/`geph5-client` plus the
+//!         packaging-owned `install-host-manager.sh` to a host-visible dir and run it
+//!         on the host via `flatpak-spawn --host`. All install/cleanup *policy* lives
+//!         in that bundled script (owned by gephgui-pkg), not here and not in geph5.
+//!         The staging dir is user-writable, so the elevated side copies each file
+//!         into a root-owned temp dir and checks it against the hash of its `/app`
+//!         original before running anything.
+//!
+//! Windows logic: the installer already registered the "Geph Manager" scheduled task
+//! (boot-triggered, SYSTEM), so normally the manager is up before any user logs in.
+//! But AV / "PC cleaner" tools sometimes delete autostart scheduled tasks outright;
+//! without a repair path that breaks Geph permanently until a reinstall. When the
+//! manager doesn't answer (after a short grace period for boot races), we run the
+//! same orchestration with UAC in place of pkexec: explain → `ShellExecuteExW`
+//! ("runas") on the sibling `geph5.exe register-manager` → wait for the named pipe
+//! to answer. Since the GUI autostarts at every logon ({commonstartup} shortcut in
+//! setup.iss), this heals a deleted task at the next logon or app launch.
+//!
+//! The orchestration (detect → dialog → elevate → result) is generic; only the
+//! command wrapping and the post-install relaunch differ between native and Flatpak.
+//! It lives in flow.rs as a state machine over probe/elevator/dialog/relauncher
+//! traits, so it can be unit-tested with fakes; this file supplies the real ones.
+//! On Flatpak, when a fresh sandbox is needed to pick up `/run/geph`, we hand off to
+//! a new instance automatically via a host-side `flatpak run`; the "reopen Geph"
+//! dialog remains only as a fallback when that handoff can't be arranged.
+//!
+//! Each step is reported to splash.rs, whose progress window keeps startup from
+//! looking hung while we wait on prompts, the installer, and the service.
+//!
+//! `uninstall_manager` is the reverse, for users who want the service gone: the
+//! same single elevation, then `geph5 unregister-manager` (plus, on Flatpak, the
+//! host binaries and cleanup timer the install script put there).
+//!
+//! Native Linux and Windows have no bundled copy to compare hashes with, so there
+//! "outdated" means the manager answers but speaks a different protocol revision
+//! (`manager::protocol_mismatch`), e.g. after a partial upgrade. That gets the same
+//! repair flow rather than failing later with opaque RPC errors.
+//!
+//! The same orchestration is reused at runtime by `repair_manager`, when the tray's
+//! state poll notices the manager has gone away while the GUI is open.
+//!
+//! For fleet deployments, `--setup-manager [--yes]` (`setup_manager_cli`) runs the
+//! same install headlessly: no dialogs, non-interactive elevation, progress on
+//! stderr, and a distinct exit code for success, failure, and "not confirmed".
+//! Administrators can pre-approve it (and skip the explanation dialog in the GUI)
+//! with `GEPH_UNATTENDED_SETUP=1` or a policy file; see `unattended_policy`.
+
+#[cfg(target_os = "linux")]
+use std::os::unix::fs::PermissionsExt;
+#[cfg(target_os = "linux")]
+use std::path::PathBuf;
+#[cfg(target_os = "linux")]
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use anyhow::Context;
+
+use crate::diagnose::{self, Cause};
+use crate::dialog::{self, Level};
+#[cfg(target_os = "linux")]
+use crate::elevate::Elevator;
+use crate::l10n::{self, Lang, tr};
+use crate::splash::{self, Step};
+use flow::{Dialogs, Flow, Outcome, Platform, Probe, Relauncher};
+
+mod flow;
+
+/// Ensure the host manager is installed, current, and answering. Returns `true` if
+/// the GUI should continue starting up, or `false` if it should exit now (the user
+/// quit, or a Flatpak first-run/relaunch is required so the `/run/geph` bind-mount
+/// picks up the freshly-created control socket).
+pub fn ensure_manager() -> bool {
+    let platform = Platform::current();
+    #[cfg(target_os = "linux")]
+    if platform == Platform::Flatpak
+        && let Some(data_dir) = dirs::data_dir()
+    {
+        clean_stale_staging(&data_dir);
+    }
+    match system_flow(platform, |flow| flow.ensure()) {
+        // Native and Windows continue even if the service didn't come up; the GUI
+        // surfaces its own "can't reach manager" error.
+        Outcome::Ready | Outcome::Unavailable => true,
+        Outcome::Declined | Outcome::Relaunch => false,
+    }
+}
+
+impl Platform {
+    fn current() -> Self {
+        #[cfg(target_os = "linux")]
+        if std::env::var_os("FLATPAK_ID").is_some() {
+            return Platform::Flatpak;
+        }
+        if cfg!(target_os = "windows") {
+            Platform::Windows
+        } else {
+            Platform::Native
+        }
+    }
+}
+
+/// Run `f` over a `Flow` wired to the real system.
+fn system_flow(platform: Platform, f: impl FnOnce(&Flow) -> Outcome) -> Outcome {
+    f(&Flow {
+        platform,
+        unattended: unattended_policy(),
+        probe: &System(platform),
+        elevator: &System(platform),
+        dialogs: &System(platform),
+        relauncher: &System(platform),
+    })
+}
+
+/// The real side effects behind `Flow`'s traits.
+struct System(Platform);
+
+impl Probe for System {
+    fn reachable(&self) -> bool {
+        reachable()
+    }
+
+    fn outdated(&self) -> bool {
+        match self.0 {
+            #[cfg(target_os = "linux")]
+            Platform::Flatpak => flatpak_manager_stale(),
+            // After a partial upgrade the running manager may speak a different
+            // protocol than we do; re-registering restarts it from the installed
+            // binary.
+            _ => protocol_mismatch(),
+        }
+    }
+
+    fn diagnose(&self) -> Cause {
+        diagnose::diagnose()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration)
+    }
+}
+
+impl flow::Elevator for System {
+    fn install(&self) -> anyhow::Result<()> {
+        #[cfg(target_os = "linux")]
+        {
+            do_install(self.0 == Platform::Flatpak)
+        }
+        #[cfg(target_os = "windows")]
+        {
+            do_install_windows()
+        }
+    }
+}
+
+impl Dialogs for System {
+    fn explain(&self, cause: &Cause) -> bool {
+        explain_dialog(cause)
+    }
+
+    fn retry(&self, err: &str) -> bool {
+        error_retry_dialog(err)
+    }
+
+    fn reopen(&self) {
+        relaunch_dialog()
+    }
+}
+
+impl Relauncher for System {
+    fn relaunch(&self) -> bool {
+        #[cfg(target_os = "linux")]
+        {
+            auto_relaunch()
+        }
+        #[cfg(target_os = "windows")]
+        {
+            false
+        }
+    }
+}
+
+/// Exit codes of `--setup-manager`, distinct so deployment scripts can branch.
+pub const SETUP_SUCCEEDED: i32 = 0;
+pub const SETUP_FAILED: i32 = 1;
+pub const SETUP_DECLINED: i32 = 2;
+
+/// Where administrators can pre-approve unattended setup, as
+/// `{"unattended_setup": true}`.
+#[cfg(target_os = "linux")]
+const POLICY_FILE: &str = "/etc/geph/policy.json";
+#[cfg(target_os = "windows")]
+const POLICY_FILE: &str = r"C:\ProgramData\Geph\policy.json";
+
+/// Has an administrator pre-approved installing the manager without asking, via
+/// `GEPH_UNATTENDED_SETUP=1` or the policy file? In the GUI this skips only the
+/// explanation dialog; the elevation prompt itself still appears.
+pub fn unattended_policy() -> bool {
+    if std::env::var("GEPH_UNATTENDED_SETUP").is_ok_and(|v| v == "1") {
+        return true;
+    }
+    std::fs::read(POLICY_FILE)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice::<serde_json::Value>(&bytes).ok())
+        .and_then(|policy| policy["unattended_setup"].as_bool())
+        .unwrap_or(false)
+}
+
+/// `--setup-manager [--yes]`: install and verify the host manager with no GUI at
+/// all, for scripted deployments (Ansible and the like). Progress goes to
+/// stderr; the return value is the process exit code (`SETUP_*`). Elevation is
+/// non-interactive: run it as root / from an elevated prompt, or (Linux) with
+/// passwordless sudo.
+pub fn setup_manager_cli(yes: bool) -> i32 {
+    fn log(msg: impl std::fmt::Display) {
+        eprintln!("[setup-manager] {msg}");
+    }
+
+    if !(yes || unattended_policy()) {
+        log(format!(
+            "not confirmed; pass --yes, set GEPH_UNATTENDED_SETUP=1, or set \
+             \"unattended_setup\": true in {POLICY_FILE}"
+        ));
+        return SETUP_DECLINED;
+    }
+
+    log("checking the background service");
+    #[cfg(target_os = "linux")]
+    let is_flatpak = std::env::var_os("FLATPAK_ID").is_some();
+    #[cfg(target_os = "linux")]
+    let needs_install = !reachable()
+        || if is_flatpak {
+            flatpak_manager_stale()
+        } else {
+            protocol_mismatch()
+        };
+    #[cfg(target_os = "windows")]
+    let needs_install = !reachable() || protocol_mismatch();
+    if !needs_install {
+        log("already installed, current, and answering");
+        return SETUP_SUCCEEDED;
+    }
+    log(format!(
+        "needs setup: {}",
+        diagnose::diagnose().summary(Lang::En)
+    ));
+
+    log("installing");
+    #[cfg(target_os = "linux")]
+    let result = install_with(is_flatpak, &Elevator::NonInteractive);
+    #[cfg(target_os = "windows")]
+    let result = register_windows_direct();
+    if let Err(err) = result {
+        log(format!("install failed: {err:#}"));
+        return SETUP_FAILED;
+    }
+
+    log("waiting for the service to answer");
+    if wait_reachable() {
+        log("done");
+        return SETUP_SUCCEEDED;
+    }
+    let cause = diagnose::diagnose();
+    #[cfg(target_os = "linux")]
+    if cause == Cause::FlatpakMountMissing {
+        // Running on the host; this sandbox just can't see it until Geph restarts.
+        log("done (the service is running on the host)");
+        return SETUP_SUCCEEDED;
+    }
+    log(format!(
+        "the service did not come up: {}",
+        cause.summary(Lang::En)
+    ));
+    SETUP_FAILED
+}
+
+/// The sibling `geph5.exe` ({app} in setup.iss).
+#[cfg(target_os = "windows")]
+fn sibling_geph5() -> anyhow::Result<std::path::PathBuf> {
+    let geph5 = std::env::current_exe()
+        .context("cannot locate our own executable")?
+        .with_file_name("geph5.exe");
+    anyhow::ensure!(
+        geph5.exists(),
+        "{} is missing; please reinstall Geph",
+        geph5.display()
+    );
+    Ok(geph5)
+}
+
+/// Unattended counterpart of `do_install_windows`: no UAC prompt, so this only
+/// works from an already-elevated process (an admin shell, a deployment agent).
+#[cfg(target_os = "windows")]
+fn register_windows_direct() -> anyhow::Result<()> {
+    use windows_sys::Win32::UI::Shell::IsUserAnAdmin;
+    anyhow::ensure!(
+        unsafe { IsUserAnAdmin() } != 0,
+        "unattended setup must be run from an elevated (administrator) process"
+    );
+    let status = std::process::Command::new(sibling_geph5()?)
+        .arg("register-manager")
+        .status()
+        .context("running geph5.exe register-manager")?;
+    anyhow::ensure!(status.success(), "register-manager exited with {status}");
+    Ok(())
+}
+
+/// Run the sibling `geph5.exe register-manager` elevated via the UAC "runas" verb
+/// (std `Command` cannot request elevation) and wait for it to finish. Deliberately
+/// no cmd/powershell intermediary: a GUI app spawning a hidden shell is a classic
+/// malware heuristic, and this binary already lives on Defender's naughty step.
+#[cfg(target_os = "windows")]
+fn do_install_windows() -> anyhow::Result<()> {
+    run_geph5_elevated("register-manager")
+}
+
+/// Run the sibling `geph5.exe <command>` through UAC and wait for it to succeed.
+#[cfg(target_os = "windows")]
+fn run_geph5_elevated(command: &str) -> anyhow::Result<()> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Foundation::CloseHandle;
+    use windows_sys::Win32::System::Threading::{
+        GetExitCodeProcess, INFINITE, WaitForSingleObject,
+    };
+    use windows_sys::Win32::UI::Shell::{
+        SEE_MASK_NOASYNC, SEE_MASK_NOCLOSEPROCESS, SHELLEXECUTEINFOW, ShellExecuteExW,
+    };
+
+    let geph5 = sibling_geph5()?;
+
+    fn wide(s: &std::ffi::OsStr) -> Vec<u16> {
+        s.encode_wide().chain(std::iter::once(0)).collect()
+    }
+    let verb = wide("runas".as_ref());
+    let file = wide(geph5.as_os_str());
+    let params = wide(command.as_ref());
+
+    let mut info: SHELLEXECUTEINFOW = unsafe { std::mem::zeroed() };
+    info.cbSize = std::mem::size_of::<SHELLEXECUTEINFOW>() as u32;
+    // NOCLOSEPROCESS: hand us the process handle so we can wait for the exit code.
+    // NOASYNC: resolve the launch synchronously (we're not on a COM/STA UI thread).
+    info.fMask = SEE_MASK_NOCLOSEPROCESS | SEE_MASK_NOASYNC;
+    info.lpVerb = verb.as_ptr();
+    info.lpFile = file.as_ptr();
+    info.lpParameters = params.as_ptr();
+    info.nShow = 0; // SW_HIDE: geph5.exe is a console binary; don't flash a window
+
+    splash::set_step(Step::WaitingForElevation);
+    let ok = unsafe { ShellExecuteExW(&mut info) };
+    if ok == 0 || info.hProcess.is_null() {
+        // Most commonly ERROR_CANCELLED: the user declined the UAC prompt.
+        anyhow::bail!("elevation was declined or failed");
+    }
+    // ShellExecuteExW returns once UAC has approved and the process started.
+    splash::set_step(Step::Installing);
+    let exit_code = unsafe {
+        WaitForSingleObject(info.hProcess, INFINITE);
+        let mut code: u32 = 1;
+        GetExitCodeProcess(info.hProcess, &mut code);
+        CloseHandle(info.hProcess);
+        code
+    };
+    anyhow::ensure!(exit_code == 0, "{command} exited with code {exit_code}");
+    Ok(())
+}
+
+/// Bundled (in-sandbox) locations of the host manager binaries and packaging assets.
+#[cfg(target_os = "linux")]
+const APP_GEPH5: &str = "/app/bin/geph5";
+#[cfg(target_os = "linux")]
+const APP_GEPH5_CLIENT: &str = "/app/bin/geph5-client";
+#[cfg(target_os = "linux")]
+const APP_LIBEXEC: &str = "/app/libexec/geph";
+
+/// Where the manager binaries live on the host after installation.
+#[cfg(target_os = "linux")]
+pub const HOST_GEPH5: &str = "/usr/local/bin/geph5";
+#[cfg(target_os = "linux")]
+const HOST_GEPH5_CLIENT: &str = "/usr/local/bin/geph5-client";
+
+/// Files staged out of the Flatpak into a host-visible dir for the privileged
+/// installer. The first two are the static binaries; the rest are packaging assets.
+#[cfg(target_os = "linux")]
+const STAGED_FILES: &[(&str, &str)] = &[
+    (APP_GEPH5, "geph5"),
+    (APP_GEPH5_CLIENT, "geph5-client"),
+    (
+        "/app/libexec/geph/install-host-manager.sh",
+        "install-host-manager.sh",
+    ),
+    (
+        "/app/libexec/geph/geph-cleanup.service",
+        "geph-cleanup.service",
+    ),
+    ("/app/libexec/geph/geph-cleanup.timer", "geph-cleanup.timer"),
+    (
+        "/app/libexec/geph/geph-flatpak-watchdog.sh",
+        "geph-flatpak-watchdog.sh",
+    ),
+];
+
+/// Repair a manager that went away while the GUI was running (see tray.rs): the
+/// same explain → elevate → wait orchestration as at startup, minus the exit.
+/// Returns whether the manager answers again. Blocks on dialogs, so call it off
+/// the event-loop thread.
+pub fn repair_manager() -> bool {
+    let platform = Platform::current();
+    match system_flow(platform, |flow| flow.repair()) {
+        Outcome::Ready => true,
+        Outcome::Unavailable | Outcome::Declined => false,
+        // This sandbox can't see the manager no matter what; only a fresh
+        // instance can. The manager is down, so there's no tunnel to keep alive
+        // by staying.
+        Outcome::Relaunch => std::process::exit(0),
+    }
+}
+
+/// Set while `uninstall_manager` runs.
+static UNINSTALLING: AtomicBool = AtomicBool::new(false);
+
+/// Remove the background service again, on the user's request from the settings
+/// page: confirm, disconnect, elevate once to unregister it (and, on Flatpak, to
+/// remove what `install-host-manager.sh` put on the host), check that it's really
+/// gone, then offer to erase the GUI's own data. The GUI is useless without the
+/// manager, so this exits the process once the service is gone; the next launch
+/// offers to set it up again. Blocks on dialogs, so call it off the event-loop
+/// thread. At most one uninstall runs at a time; a second request while the
+/// first is still in its dialogs is ignored.
+pub fn uninstall_manager() {
+    if UNINSTALLING.swap(true, Ordering::Relaxed) {
+        return;
+    }
+    uninstall_manager_inner();
+    UNINSTALLING.store(false, Ordering::Relaxed);
+}
+
+fn uninstall_manager_inner() {
+    if !confirm_uninstall_dialog() {
+        return;
+    }
+    crate::pause::cancel();
+    if let Err(err) = geph5_rt::block_on(crate::manager::stop_daemon()) {
+        tracing::warn!(err = debug(err), "could not disconnect before uninstalling");
+    }
+    loop {
+        match do_uninstall() {
+            Ok(()) => break,
+            Err(err) => {
+                if !uninstall_failed_dialog(&format!("{err:#}")) {
+                    return;
+                }
+            }
+        }
+    }
+    // The manager stops its control endpoint on the way out; make sure it did.
+    let gone = (0..40).any(|_| {
+        std::thread::sleep(Duration::from_millis(250));
+        !reachable()
+    });
+    if !gone {
+        uninstall_failed_dialog(tr("uninstall.still_running"));
+        return;
+    }
+    if clear_data_dialog() {
+        clear_gui_data();
+    }
+    std::process::exit(0);
+}
+
+/// Unregister the manager, elevated once.
+#[cfg(target_os = "linux")]
+fn do_uninstall() -> anyhow::Result<()> {
+    let elevator = Elevator::detect().context(
+        "no way to get administrator privileges was found: install a polkit \
+         authentication agent, or set $TERMINAL to your terminal emulator",
+    )?;
+    let is_flatpak = std::env::var_os("FLATPAK_ID").is_some();
+    // Native: the `.deb` owns the binary, so only the service goes. Flatpak: the
+    // binaries and the cleanup timer that `install-host-manager.sh` installed are
+    // ours to remove too (the timer would otherwise only do so once the Flatpak
+    // itself is uninstalled).
+    const UNINSTALL: &str = r#"set -u
+"$1" unregister-manager || exit $?
+[ "$2" = flatpak ] || exit 0
+for unit in geph-cleanup.timer geph-cleanup.service; do
+    path=$(systemctl show -P FragmentPath "$unit" 2>/dev/null)
+    systemctl disable --now "$unit" 2>/dev/null
+    [ -n "$path" ] && rm -f -- "$path"
+done
+systemctl daemon-reload
+rm -f -- "$3" "$4""#;
+    let (geph5, kind) = if is_flatpak {
+        (HOST_GEPH5, "flatpak")
+    } else {
+        ("geph5", "native")
+    };
+    elevator
+        .run(
+            "sh",
+            &[
+                "-c".as_ref(),
+                UNINSTALL.as_ref(),
+                "sh".as_ref(),
+                geph5.as_ref(),
+                kind.as_ref(),
+                HOST_GEPH5.as_ref(),
+                HOST_GEPH5_CLIENT.as_ref(),
+            ],
+        )
+        .context("running geph5 unregister-manager")
+}
+
+/// Unregister the "Geph Manager" task through UAC. The binaries belong to the
+/// installer and go with "Uninstall Geph" as usual.
+#[cfg(target_os = "windows")]
+fn do_uninstall() -> anyhow::Result<()> {
+    run_geph5_elevated("unregister-manager")
+}
+
+/// Erase what the GUI itself keeps on this machine: the web UI's storage (the
+/// login among it), native preferences, launch-at-login, and cached updates.
+fn clear_gui_data() {
+    if let Err(err) = crate::prefs::reset() {
+        tracing::warn!(err = debug(err), "could not remove preferences");
+    }
+    if let Err(err) = crate::autostart::set_enabled(false) {
+        tracing::warn!(err = debug(err), "could not disable launch at login");
+    }
+    if let Err(err) = crate::autoupdate::clear_cache() {
+        tracing::warn!(err = debug(err), "could not clear the update cache");
+    }
+    // The webview clears its storage on the UI thread, and we exit right after
+    // this returns, so wait for the script's callback to say it's done. A webview
+    // that never answers (e.g. it's shutting down) is given a few seconds.
+    let (done_send, done_recv) = flume::bounded(1);
+    crate::mtbus::mt_enqueue(move |wv, _| {
+        let _ = wv.clear_all_browsing_data();
+        let cleared = wv.evaluate_script_with_callback("localStorage.clear()", move |_| {
+            let _ = done_send.send(());
+        });
+        if let Err(err) = cleared {
+            tracing::warn!(err = debug(err), "could not clear the webview's storage");
+        }
+    });
+    if done_recv.recv_timeout(Duration::from_secs(5)).is_err() {
+        tracing::warn!("timed out waiting for the webview to clear its storage");
+    }
+}
+
+fn confirm_uninstall_dialog() -> bool {
+    dialog::confirm(
+        Level::Warning,
+        tr("uninstall.title"),
+        tr("uninstall.body"),
+        tr("uninstall.button"),
+        tr("common.cancel"),
+    )
+}
+
+fn clear_data_dialog() -> bool {
+    dialog::confirm(
+        Level::Info,
+        tr("uninstall.done_title"),
+        tr("uninstall.erase_body"),
+        tr("uninstall.erase"),
+        tr("uninstall.keep"),
+    )
+}
+
+/// Report an uninstall failure. Returns `true` if the user wants to retry.
+fn uninstall_failed_dialog(err: &str) -> bool {
+    dialog::confirm(
+        Level::Error,
+        tr("uninstall.failed_title"),
+        &tr("uninstall.failed_body").replace("{}", err),
+        tr("common.retry"),
+        tr("common.cancel"),
+    )
+}
+
+/// Wait up to ~10s for a (re)started manager to bind its control endpoint.
+fn wait_reachable() -> bool {
+    splash::set_step(Step::StartingService);
+    for _ in 0..40 {
+        if reachable() {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(250));
+    }
+    false
+}
+
+/// Does the answering manager speak a protocol revision we don't? A manager that
+/// doesn't answer isn't, as far as this goes; `reachable` covers that.
+pub fn protocol_mismatch() -> bool {
+    let mismatch = geph5_rt::block_on(crate::manager::protocol_mismatch()) == Some(true);
+    if mismatch {
+        tracing::warn!("the manager speaks a different control protocol revision");
+    }
+    mismatch
+}
+
+/// Can we reach the manager's control socket right now?
+fn reachable() -> bool {
+    geph5_rt::block_on(crate::manager::manager_reachable())
+}
+
+/// On Flatpak, is the host-installed manager a different build than the one we bundle?
+/// Compares content hashes (no version RPC needed). If we can't read our own bundled
+/// binaries we conservatively report "not stale"; if the host copy is missing or
+/// unreadable we report "stale" so it gets (re)installed.
+#[cfg(target_os = "linux")]
+pub fn flatpak_manager_stale() -> bool {
+    let bundled = match (file_sha256(APP_GEPH5), file_sha256(APP_GEPH5_CLIENT)) {
+        (Some(a), Some(b)) => vec![a, b],
+        _ => return false,
+    };
+    match host_sha256(&[HOST_GEPH5, HOST_GEPH5_CLIENT]) {
+        Some(installed) => installed != bundled,
+        None => true,
+    }
+}
+
+/// SHA-256 (hex) of a file we can read directly (in-sandbox bundled binaries).
+#[cfg(target_os = "linux")]
+fn file_sha256(path: &str) -> Option<String> {
+    let bytes = std::fs::read(path).ok()?;
+    Some(hex::encode(hmac_sha256::Hash::hash(&bytes)))
+}
+
+/// SHA-256 (hex) of host files, via `flatpak-spawn --host sha256sum`. The host
+/// binaries are world-readable, so the unprivileged host spawn can hash them.
+/// Returns one hash per input path, in order, or `None` if any couldn't be hashed.
+#[cfg(target_os = "linux")]
+fn host_sha256(paths: &[&str]) -> Option<Vec<String>> {
+    let out = Command::new("flatpak-spawn")
+        .arg("--host")
+        .arg("sha256sum")
+        .args(paths)
+        .output()
+        .ok()?;
+    if !out.status.success() {
+        return None;
+    }
+    let text = String::from_utf8_lossy(&out.stdout);
+    let hashes: Vec<String> = text
+        .lines()
+        .filter_map(|line| line.split_whitespace().next().map(str::to_string))
+        .collect();
+    (hashes.len() == paths.len()).then_some(hashes)
+}
+
+/// Perform the privileged install (single elevation), prompting however this
+/// session can.
+#[cfg(target_os = "linux")]
+fn do_install(is_flatpak: bool) -> anyhow::Result<()> {
+    let elevator = Elevator::detect().context(
+        "no way to get administrator privileges was found: install a polkit \
+         authentication agent, or set $TERMINAL to your terminal emulator",
+    )?;
+    install_with(is_flatpak, &elevator)
+}
+
+#[cfg(target_os = "linux")]
+fn install_with(is_flatpak: bool, elevator: &Elevator) -> anyhow::Result<()> {
+    // The elevated command runs the install right after the password prompt, and
+    // we can't tell the two apart from out here.
+    splash::set_step(Step::WaitingForElevation);
+    if is_flatpak {
+        let staged = stage_assets().context("staging the installer to a host-visible dir")?;
+        // `flatpak uninstall` removes the per-app data dir; the sandbox's $HOME *is*
+        // that dir at its real host path, so pass it as the self-cleanup "owner".
+        let owner = std::env::var("HOME").unwrap_or_else(|_| "/".into());
+        let mut args: Vec<&std::ffi::OsStr> = vec![
+            "-c".as_ref(),
+            VERIFY_AND_INSTALL.as_ref(),
+            "sh".as_ref(),
+            staged.dir.as_os_str(),
+            owner.as_ref(),
+        ];
+        for (name, sha256) in &staged.hashes {
+            args.push(name.as_ref());
+            args.push(sha256.as_ref());
+        }
+        let result = elevator
+            .run("sh", &args)
+            .context("running install-host-manager.sh on the host");
+        // The root-side copy is what ran; ours is no longer needed either way.
+        let _ = std::fs::remove_dir_all(&staged.dir);
+        result
+    } else {
+        // Native: the `.deb` already installed `geph5`; just register the service.
+        elevator
+            .run("geph5", &["register-manager".as_ref()])
+            .context("running geph5 register-manager")
+    }
+}
+
+/// Runs as root: `sh -c VERIFY_AND_INSTALL sh <staging> <owner> [<name> <sha256>]…`.
+/// The staging dir is writable by the (unprivileged) user, so anything running as
+/// them could swap a file between our copy and this point. Hence nothing in it is
+/// executed in place: every file is first copied into a fresh root-owned `mktemp`
+/// dir, checked there against the hash of the bundled `/app` original, and only
+/// then is the copied installer run, pointed at the copied assets.
+#[cfg(target_os = "linux")]
+const VERIFY_AND_INSTALL: &str = r#"set -eu
+staging=$1; owner=$2; shift 2
+work=$(mktemp -d)
+trap 'rm -rf "$work"' EXIT
+while [ $# -ge 2 ]; do
+    name=$1; sum=$2; shift 2
+    cp -- "$staging/$name" "$work/$name"
+    if ! printf '%s  %s\n' "$sum" "$work/$name" | sha256sum -c --status -; then
+        echo "geph: staged $name does not match the bundled copy; refusing to install" >&2
+        exit 1
+    fi
+    chmod 0755 "$work/$name"
+done
+sh "$work/install-host-manager.sh" "$work" "$owner""#;
+
+/// The installer files staged for one install, with the SHA-256 of each file's
+/// bundled `/app` original (not of the staged copy, which could already have
+/// been tampered with).
+#[cfg(target_os = "linux")]
+struct Staged {
+    dir: PathBuf,
+    hashes: Vec<(&'static str, String)>,
+}
+
+/// Copy the bundled binaries and packaging assets into a fresh host-visible staging
+/// dir (under `$XDG_DATA_HOME`, which maps to the same absolute path on the host).
+/// The privileged side verifies and copies everything out of there before use.
+#[cfg(target_os = "linux")]
+fn stage_assets() -> anyhow::Result<Staged> {
+    let data_dir = dirs::data_dir().context("no data dir")?;
+    clean_stale_staging(&data_dir);
+    let staging = data_dir.join(format!("{STAGING_PREFIX}{}", std::process::id()));
+    std::fs::create_dir_all(&staging).with_context(|| format!("mkdir {}", staging.display()))?;
+    let mut hashes = Vec::with_capacity(STAGED_FILES.len());
+    for (src, name) in STAGED_FILES {
+        let sha256 = file_sha256(src).with_context(|| format!("hash {src}"))?;
+        let dst = staging.join(name);
+        std::fs::copy(src, &dst).with_context(|| format!("copy {src} -> {}", dst.display()))?;
+        std::fs::set_permissions(&dst, std::fs::Permissions::from_mode(0o755))
+            .with_context(|| format!("chmod {}", dst.display()))?;
+        hashes.push((*name, sha256));
+    }
+    Ok(Staged {
+        dir: staging,
+        hashes,
+    })
+}
+
+/// Staging dirs are `$XDG_DATA_HOME/geph-host-install.<pid>`; older builds used
+/// the bare name.
+#[cfg(target_os = "linux")]
+const STAGING_PREFIX: &str = "geph-host-install.";
+
+/// How old a staging dir must be before `clean_stale_staging` takes it for
+/// abandoned. Well past any install, however long its password prompt sat there.
+#[cfg(target_os = "linux")]
+const STAGING_MAX_AGE: Duration = Duration::from_secs(3600);
+
+/// Remove staging dirs left behind by installs that crashed or were killed.
+/// `--setup-manager` runs outside the single-instance lock, so another process
+/// may be installing from its own dir right now; only old ones are touched.
+#[cfg(target_os = "linux")]
+fn clean_stale_staging(data_dir: &std::path::Path) {
+    let Ok(entries) = std::fs::read_dir(data_dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name != "geph-host-install" && !name.starts_with(STAGING_PREFIX) {
+            continue;
+        }
+        let age = entry
+            .metadata()
+            .and_then(|meta| meta.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok());
+        if age.is_some_and(|age| age >= STAGING_MAX_AGE) {
+            let _ = std::fs::remove_dir_all(entry.path());
+        }
+    }
+}
+
+/// Explain the privileged setup and ask permission. Returns `true` to proceed.
+fn explain_dialog(cause: &Cause) -> bool {
+    let description = format!(
+        "{}\n\n{} {}",
+        cause.summary(l10n::current()),
+        tr("setup.body"),
+        elevation_hint()
+    );
+    dialog::confirm(
+        Level::Info,
+        tr("setup.title"),
+        &description,
+        tr("setup.button"),
+        tr("common.quit"),
+    )
+}
+
+/// What the user is about to see when we elevate, so the prompt isn't a surprise.
+fn elevation_hint() -> &'static str {
+    #[cfg(target_os = "windows")]
+    {
+        tr("setup.hint_windows")
+    }
+    #[cfg(target_os = "linux")]
+    {
+        tr(match Elevator::detect() {
+            Some(Elevator::Pkexec | Elevator::Run0) => "setup.hint_password",
+            Some(Elevator::TerminalSudo { .. }) => "setup.hint_terminal",
+            Some(Elevator::NonInteractive) => unreachable!("detect() never picks it"),
+            None => "setup.hint_none",
+        })
+    }
+}
+
+/// Report an install failure and offer to retry. Returns `true` to retry.
+fn error_retry_dialog(err: &str) -> bool {
+    dialog::confirm(
+        Level::Error,
+        tr("setup.failed_title"),
+        &tr("setup.failed_body").replace("{}", err),
+        tr("common.retry"),
+        tr("common.quit"),
+    )
+}
+
+/// Hand this session off to a fresh Flatpak instance, whose sandbox will bind-mount
+/// the now-existing `/run/geph`. Returns whether the handoff was scheduled; the
+/// caller must exit promptly either way, since the helper launches the new instance
+/// only once this one is gone.
+#[cfg(target_os = "linux")]
+fn auto_relaunch() -> bool {
+    let Some(app_id) = std::env::var_os("FLATPAK_ID") else {
+        return false;
+    };
+    // The relaunch has to be driven from the host: only a brand-new `flatpak run`
+    // gets a sandbox whose `/run/geph` bind-mount exists. Two timing constraints
+    // shape the shell helper:
+    //   * The subshell is backgrounded so the outer `sh` — and with it our
+    //     flatpak-spawn child — returns immediately, instead of tethering this
+    //     dying sandbox to the new instance's whole lifetime.
+    //   * The new instance can only win the single-instance port (main.rs) after
+    //     this process exits, and it *quits* if it loses that race. So the helper
+    //     polls `flatpak ps` until our instance is gone (bounded at ~10s) before
+    //     launching the replacement.
+    const HANDOFF: &str = r#"(
+        for _ in $(seq 40); do
+            flatpak ps --columns=application 2>/dev/null | grep -qF "$1" || break
+            sleep 0.25
+        done
+        exec flatpak run "$1" >/dev/null 2>&1
+    ) &"#;
+    Command::new("flatpak-spawn")
+        .args(["--host", "sh", "-c", HANDOFF, "sh"])
+        .arg(&app_id)
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false)
+}
+
+/// Tell the user setup is done and they should reopen Geph — fallback for when
+/// `auto_relaunch` couldn't schedule the handoff (in practice Flatpak only).
+fn relaunch_dialog() {
+    dialog::show(
+        Level::Info,
+        tr("setup.done_title"),
+        tr("setup.reopen_body"),
+        &[tr("common.ok")],
+    );
+}
@@ -0,0 +1,253 @@
+//! Why can't we reach the manager? (Linux + Windows.)
+//!
+//! `manager_reachable()` is a yes/no answer, which is all the bootstrap needs to
+//! decide *whether* to act, but not enough to tell the user what is wrong or for
+//! support to tell from a debug pack. `diagnose` walks the likely causes from the
+//! bottom up (is the binary there, is the service registered and running, is the
+//! control socket there and can we open it, is it the right build or protocol) and
+//! returns the first one that explains the failure, with a remedy phrased for the
+//! bootstrap dialog. `report` dumps every probe for debug packs.
+
+#[cfg(target_os = "linux")]
+use crate::elevate::{host_command, on_host_path};
+use crate::l10n::{Lang, tr_in};
+
+/// The systemd unit `geph5 register-manager` installs.
+#[cfg(target_os = "linux")]
+const MANAGER_UNIT: &str = "geph-manager.service";
+/// The manager's control socket, as seen both on the host and (via the
+/// `/run/geph` bind-mount) inside the Flatpak sandbox.
+#[cfg(target_os = "linux")]
+const CONTROL_SOCKET: &str = "/run/geph/control.sock";
+/// The scheduled task the Windows installer registers.
+#[cfg(target_os = "windows")]
+const MANAGER_TASK: &str = "Geph Manager";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Cause {
+    /// The manager binary isn't installed where we expect it.
+    BinaryMissing,
+    /// The binary is there but the service (systemd unit / scheduled task) isn't.
+    ServiceMissing,
+    /// The service is registered but not running.
+    ServiceInactive,
+    /// The service is registered but failed; carries systemd's `Result=`.
+    ServiceFailed(String),
+    /// The service runs but hasn't created its control socket.
+    SocketMissing,
+    /// The socket exists but we aren't allowed to connect to it.
+    PermissionDenied,
+    /// The installed manager isn't the build we bundle (Flatpak), or speaks a
+    /// different protocol revision than this GUI (see `manager::protocol_mismatch`).
+    VersionMismatch,
+    /// Flatpak: the host socket exists, but `/run/geph` wasn't there when this
+    /// sandbox started, so the bind-mount is missing.
+    FlatpakMountMissing,
+    /// None of the above; carries whatever we learned.
+    Unknown(String),
+}
+
+impl Cause {
+    /// One user-facing sentence describing the problem and one with the remedy.
+    pub fn describe(&self, lang: Lang) -> (String, String) {
+        let (cause, detail, remedy) = match self {
+            Cause::BinaryMissing => ("cause.binary_missing", "", "remedy.install"),
+            Cause::ServiceMissing => ("cause.service_missing", "", "remedy.register"),
+            Cause::ServiceInactive => ("cause.service_inactive", "", "remedy.start"),
+            Cause::ServiceFailed(result) => {
+                ("cause.service_failed", result.as_str(), "remedy.reinstall")
+            }
+            Cause::SocketMissing => ("cause.socket_missing", "", "remedy.restart"),
+            Cause::PermissionDenied => ("cause.permission_denied", "", "remedy.permissions"),
+            Cause::VersionMismatch => ("cause.version_mismatch", "", "remedy.update"),
+            Cause::FlatpakMountMissing => ("cause.flatpak_mount_missing", "", "remedy.reopen"),
+            Cause::Unknown(detail) => ("cause.unknown", detail.as_str(), "remedy.repair"),
+        };
+        (
+            tr_in(lang, cause).replace("{}", detail),
+            tr_in(lang, remedy).to_string(),
+        )
+    }
+
+    /// `describe` as a single paragraph.
+    pub fn summary(&self, lang: Lang) -> String {
+        let (cause, remedy) = self.describe(lang);
+        format!("{cause} {remedy}")
+    }
+}
+
+/// Find the most likely reason the manager isn't answering. Blocks on a few quick
+/// subprocesses, so keep it off the event-loop thread.
+#[cfg(target_os = "linux")]
+pub fn diagnose() -> Cause {
+    let is_flatpak = std::env::var_os("FLATPAK_ID").is_some();
+    let binary_present = if is_flatpak {
+        host_test("-x", crate::bootstrap::HOST_GEPH5)
+    } else {
+        on_host_path("geph5")
+    };
+    if !binary_present {
+        return Cause::BinaryMissing;
+    }
+
+    let unit = unit_properties();
+    match unit.get("LoadState").map(String::as_str) {
+        Some("not-found") => return Cause::ServiceMissing,
+        Some(_) => match unit.get("ActiveState").map(String::as_str) {
+            Some("failed") => {
+                let result = unit.get("Result").cloned().unwrap_or_default();
+                return Cause::ServiceFailed(result);
+            }
+            Some("inactive") => return Cause::ServiceInactive,
+            _ => {}
+        },
+        // No systemd (or no `systemctl` we can run): skip straight to the socket.
+        None => {}
+    }
+
+    let socket = std::path::Path::new(CONTROL_SOCKET);
+    if !socket.exists() {
+        if is_flatpak && host_test("-S", CONTROL_SOCKET) {
+            return Cause::FlatpakMountMissing;
+        }
+        return Cause::SocketMissing;
+    }
+    if let Err(err) = std::os::unix::net::UnixStream::connect(socket) {
+        if err.kind() == std::io::ErrorKind::PermissionDenied {
+            return Cause::PermissionDenied;
+        }
+        return Cause::Unknown(err.to_string());
+    }
+
+    if (is_flatpak && crate::bootstrap::flatpak_manager_stale())
+        || crate::bootstrap::protocol_mismatch()
+    {
+        return Cause::VersionMismatch;
+    }
+    Cause::Unknown("the control socket accepts connections but the manager doesn't respond".into())
+}
+
+#[cfg(target_os = "windows")]
+pub fn diagnose() -> Cause {
+    let binary_present = std::env::current_exe()
+        .map(|exe| exe.with_file_name("geph5.exe").exists())
+        .unwrap_or(false);
+    if !binary_present {
+        return Cause::BinaryMissing;
+    }
+    // schtasks prints the status in the display language, and only the English
+    // words are known here. Anything else is reported as is rather than guessed
+    // at: calling a running task inactive would send the user down the wrong
+    // remedy.
+    match task_status() {
+        None => Cause::ServiceMissing,
+        Some(status) if status.eq_ignore_ascii_case("Running") => {
+            if crate::bootstrap::protocol_mismatch() {
+                Cause::VersionMismatch
+            } else {
+                Cause::Unknown("the manager task is running but its pipe doesn't answer".into())
+            }
+        }
+        Some(status)
+            if ["Ready", "Disabled", "Queued"]
+                .iter()
+                .any(|inactive| status.eq_ignore_ascii_case(inactive)) =>
+        {
+            Cause::ServiceInactive
+        }
+        Some(status) => Cause::Unknown(format!(
+            "the manager task's status is \"{status}\" and its pipe doesn't answer"
+        )),
+    }
+}
+
+/// Every probe's raw result, one per line, for debug packs. The diagnosis is only
+/// meaningful when `reachable` is false.
+pub fn report() -> String {
+    let cause = diagnose();
+    let mut lines = vec![
+        format!(
+            "reachable: {}",
+            geph5_rt::block_on(crate::manager::manager_reachable())
+        ),
+        format!("diagnosis: {cause:?}"),
+        format!("summary: {}", cause.summary(Lang::En)),
+        format!(
+            "protocol mismatch: {:?}",
+            geph5_rt::block_on(crate::manager::protocol_mismatch())
+        ),
+    ];
+    #[cfg(target_os = "linux")]
+    {
+        lines.push(format!(
+            "flatpak: {}",
+            std::env::var("FLATPAK_ID").unwrap_or_else(|_| "no".into())
+        ));
+        lines.push(format!(
+            "{MANAGER_UNIT}: {:?}",
+            unit_properties().into_iter().collect::<Vec<_>>()
+        ));
+        lines.push(format!(
+            "{CONTROL_SOCKET} exists in our view: {}",
+            std::path::Path::new(CONTROL_SOCKET).exists()
+        ));
+    }
+    #[cfg(target_os = "windows")]
+    {
+        lines.push(format!("{MANAGER_TASK} task status: {:?}", task_status()));
+    }
+    lines.join("\n")
+}
+
+/// `test <flag> <path>` on the host.
+#[cfg(target_os = "linux")]
+fn host_test(flag: &str, path: &str) -> bool {
+    host_command("test")
+        .args([flag, path])
+        .status()
+        .is_ok_and(|s| s.success())
+}
+
+/// The manager unit's load/active state, via `systemctl show` on the host. Empty
+/// if systemctl isn't available.
+#[cfg(target_os = "linux")]
+fn unit_properties() -> std::collections::BTreeMap<String, String> {
+    let Ok(out) = host_command("systemctl")
+        .args([
+            "show",
+            "--property=LoadState,ActiveState,SubState,Result,ExecMainStatus",
+            MANAGER_UNIT,
+        ])
+        .output()
+    else {
+        return Default::default();
+    };
+    if !out.status.success() {
+        return Default::default();
+    }
+    String::from_utf8_lossy(&out.stdout)
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+/// The manager scheduled task's status column ("Ready", "Running", "Disabled" in
+/// English; localized otherwise), or `None` if the task doesn't exist.
+#[cfg(target_os = "windows")]
+fn task_status() -> Option<String> {
+    use std::os::windows::process::CommandExt;
+    const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+    let out = std::process::Command::new("schtasks")
+        .args(["/query", "/tn", MANAGER_TASK, "/fo", "csv", "/nh"])
+        .creation_flags(CREATE_NO_WINDOW)
+        .output()
+        .ok()?;
+    if !out.status.success() {
+        return None;
+    }
+    // "\Geph Manager","<next run>","<status>"
+    let text = String::from_utf8_lossy(&out.stdout);
+    let status = text.lines().next()?.rsplit(',').next()?;
+    Some(status.trim().trim_matches('"').to_string())
+}
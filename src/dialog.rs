@@ -0,0 +1,229 @@
+//! Native message dialogs, with a fallback for systems that can't show them.
+//!
+//! Every prompt outside the webview (the bootstrap's explanation and retry
+//! dialogs, the relaunch notice, the update prompt, the tray's close/quit
+//! questions) goes through `show`. Normally that's `rfd::MessageDialog`. But on
+//! Linux, rfd's `xdg-portal` backend draws message dialogs by running `zenity`,
+//! and where zenity isn't installed (KDE-only and minimal desktops, many
+//! Flatpak runtimes) `show()` quietly returns a result matching no button: the
+//! bootstrap took that as "Quit" and the app vanished without a word.
+//!
+//! So we check for zenity up front, and without it render the same prompt in a
+//! small tao/wry window instead. A process can only ever have one event loop,
+//! and these prompts come from worker threads as well as from inside the main
+//! loop, so the window is shown by a child copy of ourselves (`--dialog <json>`,
+//! handled at the very top of `main`), which prints the chosen button's index
+//! and exits.
+
+use std::{process::Command, sync::OnceLock};
+
+use rfd::{MessageButtons, MessageDialog, MessageDialogResult, MessageLevel};
+use serde::{Deserialize, Serialize};
+
+/// Command-line flag that turns this process into a fallback dialog.
+pub const DIALOG_FLAG: &str = "--dialog";
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Level {
+    Info,
+    Warning,
+    Error,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Spec {
+    level: Level,
+    title: String,
+    body: String,
+    buttons: Vec<String>,
+}
+
+/// Show a modal message with one to three buttons, and block until the user
+/// answers. Returns the index of the button pressed, or `None` if the dialog was
+/// dismissed (or couldn't be shown at all).
+pub fn show(level: Level, title: &str, body: &str, buttons: &[&str]) -> Option<usize> {
+    assert!(
+        (1..=3).contains(&buttons.len()),
+        "dialogs have one to three buttons"
+    );
+    if native_available() {
+        show_native(level, title, body, buttons)
+    } else {
+        show_fallback(Spec {
+            level,
+            title: title.into(),
+            body: body.into(),
+            buttons: buttons.iter().map(|b| b.to_string()).collect(),
+        })
+    }
+}
+
+/// A two-button question: whether the user chose `yes`.
+pub fn confirm(level: Level, title: &str, body: &str, yes: &str, no: &str) -> bool {
+    show(level, title, body, &[yes, no]) == Some(0)
+}
+
+/// Can rfd show message dialogs here? Everywhere but Linux that's the OS's own
+/// API; on Linux it needs zenity. `GEPH_DIALOG_FALLBACK=1` forces the fallback,
+/// for testing it.
+fn native_available() -> bool {
+    static AVAILABLE: OnceLock<bool> = OnceLock::new();
+    *AVAILABLE.get_or_init(|| {
+        if std::env::var("GEPH_DIALOG_FALLBACK").is_ok_and(|v| v == "1") {
+            return false;
+        }
+        if cfg!(target_os = "linux") {
+            Command::new("zenity")
+                .arg("--version")
+                .output()
+                .is_ok_and(|out| out.status.success())
+        } else {
+            true
+        }
+    })
+}
+
+fn show_native(level: Level, title: &str, body: &str, buttons: &[&str]) -> Option<usize> {
+    let rfd_buttons = match *buttons {
+        [ok] => MessageButtons::OkCustom(ok.into()),
+        [ok, cancel] => MessageButtons::OkCancelCustom(ok.into(), cancel.into()),
+        [yes, no, cancel] => {
+            MessageButtons::YesNoCancelCustom(yes.into(), no.into(), cancel.into())
+        }
+        _ => unreachable!(),
+    };
+    let result = MessageDialog::new()
+        .set_level(match level {
+            Level::Info => MessageLevel::Info,
+            Level::Warning => MessageLevel::Warning,
+            Level::Error => MessageLevel::Error,
+        })
+        .set_title(title)
+        .set_description(body)
+        .set_buttons(rfd_buttons)
+        .show();
+    match result {
+        MessageDialogResult::Custom(label) => buttons.iter().position(|b| *b == label),
+        // Some backends report a lone custom button as plain Ok.
+        MessageDialogResult::Ok if buttons.len() == 1 => Some(0),
+        _ => None,
+    }
+}
+
+/// Run `<ourselves> --dialog <spec>` and read the answer from its stdout.
+fn show_fallback(spec: Spec) -> Option<usize> {
+    let spawned = std::env::current_exe().and_then(|exe| {
+        Command::new(exe)
+            .arg(DIALOG_FLAG)
+            .arg(serde_json::to_string(&spec).unwrap())
+            .output()
+    });
+    match spawned {
+        Ok(out) => String::from_utf8_lossy(&out.stdout).trim().parse().ok(),
+        Err(err) => {
+            // Nothing left to show it with; at least leave it in the log.
+            eprintln!(
+                "could not show dialog ({err}): {}: {}",
+                spec.title, spec.body
+            );
+            None
+        }
+    }
+}
+
+/// The `--dialog <json>` child: show the prompt in a tao/wry window, print the
+/// chosen button's index on stdout, and exit. Exits with status 1, printing
+/// nothing, if the window is closed instead.
+pub fn run_child(spec: &str) -> ! {
+    if let Err(err) = child_window(spec) {
+        eprintln!("fallback dialog failed: {err:#}");
+    }
+    std::process::exit(1)
+}
+
+/// Only returns on error; the event loop exits the process itself.
+fn child_window(spec: &str) -> anyhow::Result<()> {
+    use tao::{
+        dpi::LogicalSize,
+        event::{Event, WindowEvent},
+        event_loop::{ControlFlow, EventLoopBuilder},
+        window::WindowBuilder,
+    };
+    use wry::{WebContext, WebViewBuilder};
+
+    let spec: Spec = serde_json::from_str(spec)?;
+    let event_loop = EventLoopBuilder::<()>::with_user_event().build();
+    let window = WindowBuilder::new()
+        .with_title(&spec.title)
+        .with_inner_size(LogicalSize::new(460, 260))
+        .with_always_on_top(true)
+        .build(&event_loop)?;
+    let mut web_context = WebContext::new(dirs::cache_dir().map(|dir| dir.join("geph5-dialog")));
+    let builder = WebViewBuilder::with_web_context(&mut web_context)
+        // `<` escaped so no string in the spec can close the <script>.
+        .with_html(PAGE.replace(
+            "{spec}",
+            &serde_json::to_string(&spec)?.replace('<', "\\u003c"),
+        ))
+        .with_ipc_handler(|req| {
+            if let Ok(index) = req.body().parse::<usize>() {
+                println!("{index}");
+                std::process::exit(0);
+            }
+        });
+    #[cfg(not(target_os = "linux"))]
+    let webview = builder.build(&window)?;
+    #[cfg(target_os = "linux")]
+    let webview = {
+        use tao::platform::unix::WindowExtUnix;
+        use wry::WebViewBuilderExtUnix;
+        let vbox = window
+            .default_vbox()
+            .ok_or_else(|| anyhow::anyhow!("window has no GTK vbox"))?;
+        builder.build_gtk(vbox)?
+    };
+
+    event_loop.run(move |event, _, control_flow| {
+        let _ = (&window, &webview);
+        *control_flow = ControlFlow::Wait;
+        if let Event::WindowEvent {
+            event: WindowEvent::CloseRequested,
+            ..
+        } = event
+        {
+            std::process::exit(1);
+        }
+    })
+}
+
+const PAGE: &str = r#"<!doctype html>
+<html><head><meta charset="utf-8"><style>
+  body { font: 14px system-ui, sans-serif; margin: 0; color: #222; display: flex;
+         flex-direction: column; height: 100vh; user-select: none; }
+  main { flex: 1; overflow-y: auto; padding: 20px 20px 0; display: flex; gap: 14px; }
+  #icon { font-size: 28px; line-height: 1; }
+  .info #icon { color: #2a7ae2; } .warning #icon { color: #d98e04; } .error #icon { color: #d33; }
+  h1 { font-size: 15px; margin: 0 0 8px; }
+  p { white-space: pre-wrap; margin: 0; }
+  footer { display: flex; justify-content: flex-end; gap: 8px; padding: 14px 20px; }
+  button { min-width: 80px; padding: 5px 12px; }
+</style></head><body>
+  <main><div id="icon"></div><div><h1 id="title"></h1><p id="body"></p></div></main>
+  <footer id="buttons"></footer>
+  <script>
+    const spec = {spec};
+    document.body.className = spec.level;
+    document.getElementById("icon").textContent =
+      { info: "ℹ", warning: "⚠", error: "✖" }[spec.level];
+    document.getElementById("title").textContent = spec.title;
+    document.getElementById("body").textContent = spec.body;
+    spec.buttons.forEach((label, i) => {
+      const button = document.createElement("button");
+      button.textContent = label;
+      button.onclick = () => window.ipc.postMessage(String(i));
+      document.getElementById("buttons").appendChild(button);
+      if (i === 0) button.focus();
+    });
+  </script>
+</body></html>"#;
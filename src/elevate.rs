@@ -0,0 +1,239 @@
+//! Linux privilege elevation for the bootstrap's host-manager install.
+//!
+//! `pkexec` can only ask for a password through a polkit authentication agent.
+//! Full desktops (GNOME, KDE, Cinnamon, …) run one, but bare window managers
+//! (sway, i3, …) often don't, and pkexec then fails every single time. So rather
+//! than hardcoding pkexec we pick the first mechanism that can actually prompt:
+//!
+//!   * `pkexec`, when an agent is running;
+//!   * `run0` (systemd ≥ 256), also polkit-backed, when there's an agent but no
+//!     pkexec;
+//!   * `sudo` inside a terminal window (`$TERMINAL`, else
+//!     `x-terminal-emulator`, else `xterm`), whose tty gives sudo somewhere to
+//!     ask even with no agent at all.
+//!
+//! Unattended setup (`--setup-manager --yes`) must never prompt, so it uses
+//! `NonInteractive` instead: run directly if we're already root, else `sudo -n`.
+//!
+//! Under Flatpak every probe and every elevated command runs on the host via
+//! `flatpak-spawn --host`.
+
+use std::{
+    ffi::OsStr,
+    path::PathBuf,
+    process::{Command, Stdio},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+
+/// Names of processes that provide a polkit authentication agent: standalone
+/// agents, plus the shells that embed one. Matched against the process name,
+/// which the kernel cuts to 15 bytes (`polkit-gnome-authentication-agent-1` is
+/// `polkit-gnome-au`), never the command line: under Flatpak our own
+/// `flatpak-spawn --host pgrep …` is on the host too, with this very pattern as
+/// an argument.
+const AGENT_PATTERN: &str = "^(polkit-[a-z]+-au|lxpolkit|lxqt-policykit|xfce-polkit|mate-polkit|\
+                             hyprpolkitagent|gnome-shell|cinnamon)";
+
+/// How long we wait for the user to finish with a terminal-hosted `sudo`.
+const TERMINAL_TIMEOUT: Duration = Duration::from_secs(300);
+/// How long a terminal gets to start running our script at all.
+const TERMINAL_START_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A way to run one command as root.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Elevator {
+    Pkexec,
+    Run0,
+    TerminalSudo { terminal: String },
+    NonInteractive,
+}
+
+impl Elevator {
+    /// Pick the first mechanism that can prompt in this session, if any.
+    pub fn detect() -> Option<Self> {
+        let agent = agent_running();
+        if agent && on_host_path("pkexec") {
+            return Some(Self::Pkexec);
+        }
+        if agent && on_host_path("run0") {
+            return Some(Self::Run0);
+        }
+        if on_host_path("sudo") {
+            let terminal = std::env::var("TERMINAL")
+                .ok()
+                .filter(|t| on_host_path(t))
+                .or_else(|| {
+                    ["x-terminal-emulator", "xterm"]
+                        .into_iter()
+                        .find(|t| on_host_path(t))
+                        .map(str::to_string)
+                })?;
+            return Some(Self::TerminalSudo { terminal });
+        }
+        None
+    }
+
+    /// Run `program args…` as root, failing unless it exits successfully.
+    pub fn run(&self, program: impl AsRef<OsStr>, args: &[&OsStr]) -> anyhow::Result<()> {
+        let program = program.as_ref();
+        let status = match self {
+            Self::Pkexec | Self::Run0 => {
+                let tool = if *self == Self::Pkexec { "pkexec" } else { "run0" };
+                host_command(tool)
+                    .arg(program)
+                    .args(args)
+                    .status()
+                    .with_context(|| format!("running {tool}"))?
+                    .code()
+            }
+            Self::TerminalSudo { terminal } => run_in_terminal(terminal, program, args)?,
+            Self::NonInteractive => {
+                let mut cmd = if unsafe { libc::geteuid() } == 0 {
+                    host_command(program)
+                } else {
+                    let mut cmd = host_command("sudo");
+                    cmd.args(["-n", "--"]).arg(program);
+                    cmd
+                };
+                cmd.args(args).status().context("running sudo -n")?.code()
+            }
+        };
+        match status {
+            Some(0) => Ok(()),
+            Some(code) => anyhow::bail!("privileged command exited with code {code}"),
+            None => anyhow::bail!("privileged command was killed"),
+        }
+    }
+}
+
+/// A `Command` for `program` on the host (through `flatpak-spawn --host` when
+/// we're sandboxed).
+pub fn host_command(program: impl AsRef<OsStr>) -> Command {
+    if std::env::var_os("FLATPAK_ID").is_some() {
+        let mut cmd = Command::new("flatpak-spawn");
+        cmd.arg("--host").arg(program);
+        cmd
+    } else {
+        Command::new(program)
+    }
+}
+
+/// Is `program` on the host's `$PATH`?
+pub fn on_host_path(program: &str) -> bool {
+    host_command("sh")
+        .args(["-c", "command -v \"$1\" >/dev/null", "sh", program])
+        .status()
+        .is_ok_and(|s| s.success())
+}
+
+/// Is a polkit authentication agent running? If we can't tell (no `pgrep`),
+/// assume so, which keeps the long-standing pkexec behavior.
+fn agent_running() -> bool {
+    // procps warns about any pattern longer than a process name, alternation or
+    // not; it still matches.
+    let status = host_command("pgrep")
+        .arg(AGENT_PATTERN)
+        .stderr(Stdio::null())
+        .status();
+    match status {
+        Ok(status) => status.code() != Some(1),
+        Err(_) => true,
+    }
+}
+
+/// Run `sudo program args…` in a new terminal window and return its exit code.
+/// Many terminals hand off to a server process and return at once, so the exit
+/// code comes back through a status file rather than the terminal's own. The
+/// script first writes its pid there, so that if the user closes the window
+/// instead (killing it before it writes a code) we notice at once rather than
+/// waiting out `TERMINAL_TIMEOUT`.
+fn run_in_terminal(
+    terminal: &str,
+    program: &OsStr,
+    args: &[&OsStr],
+) -> anyhow::Result<Option<i32>> {
+    // Under Flatpak, $XDG_DATA_HOME is at the same path on the host.
+    let status_file: PathBuf = dirs::data_dir()
+        .context("no data dir")?
+        .join(format!("geph-elevate-{}.status", std::process::id()));
+    let _ = std::fs::remove_file(&status_file);
+
+    const SCRIPT: &str = r#"status=$1; shift
+echo "pid $$" >"$status"
+echo "Geph needs administrator access to set up its background service."
+sudo -- "$@"
+echo $? >"$status""#;
+    let mut cmd = host_command(terminal);
+    // Most terminals take `-e cmd args…`; these take the command directly.
+    let name = terminal.rsplit('/').next().unwrap_or(terminal);
+    match name {
+        "gnome-terminal" => {
+            cmd.arg("--");
+        }
+        "foot" | "kitty" => {}
+        _ => {
+            cmd.arg("-e");
+        }
+    }
+    cmd.args(["sh", "-c", SCRIPT, "sh"])
+        .arg(&status_file)
+        .arg(program)
+        .args(args);
+    let mut child = cmd
+        .spawn()
+        .with_context(|| format!("opening a terminal ({terminal})"))?;
+
+    let exit_code = || {
+        let code = std::fs::read_to_string(&status_file)
+            .ok()?
+            .trim()
+            .parse()
+            .ok()?;
+        let _ = std::fs::remove_file(&status_file);
+        Some(code)
+    };
+    let start = Instant::now();
+    while start.elapsed() < TERMINAL_TIMEOUT {
+        if let Some(code) = exit_code() {
+            return Ok(Some(code));
+        }
+        let text = std::fs::read_to_string(&status_file).unwrap_or_default();
+        match text.trim().strip_prefix("pid ") {
+            // It may have finished between the two reads.
+            Some(pid) if !host_process_alive(pid) => {
+                return match exit_code() {
+                    Some(code) => Ok(Some(code)),
+                    None => {
+                        let _ = std::fs::remove_file(&status_file);
+                        anyhow::bail!("the terminal was closed before sudo finished")
+                    }
+                };
+            }
+            Some(_) => {}
+            None => {
+                // A terminal that hands off exits successfully at once; one that
+                // failed to open the window doesn't.
+                if let Ok(Some(status)) = child.try_wait()
+                    && !status.success()
+                {
+                    anyhow::bail!("the terminal ({terminal}) exited with {status}");
+                }
+                if start.elapsed() > TERMINAL_START_TIMEOUT {
+                    anyhow::bail!("the terminal ({terminal}) never ran the command");
+                }
+            }
+        }
+        std::thread::sleep(Duration::from_millis(500));
+    }
+    anyhow::bail!("timed out waiting for the terminal to finish")
+}
+
+/// Is the host process `pid` still running?
+fn host_process_alive(pid: &str) -> bool {
+    host_command("sh")
+        .args(["-c", "kill -0 \"$1\" 2>/dev/null", "sh", pid])
+        .status()
+        .is_ok_and(|s| s.success())
+}
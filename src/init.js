@@ -81,3 +81,45 @@ async function jsonrpc_call(method, ...params) {
     id: 1,
   });
 }
+
+// "Background service unavailable" banner. The native side pushes
+// `geph_manager_status` events when the manager goes away or comes back (see
+// tray.rs), with localized text; Repair runs the native repair flow.
+function updateManagerBanner(status) {
+  let banner = document.getElementById("geph-manager-banner");
+  if (status.reachable) {
+    if (banner) banner.remove();
+    return;
+  }
+  if (!document.body) {
+    document.addEventListener("DOMContentLoaded", () =>
+      updateManagerBanner(status)
+    );
+    return;
+  }
+  if (!banner) {
+    banner = document.createElement("div");
+    banner.id = "geph-manager-banner";
+    banner.style.cssText =
+      "position:fixed;top:0;left:0;right:0;z-index:2147483647;padding:10px;" +
+      "background:#b3261e;color:#fff;font:14px sans-serif;display:flex;" +
+      "align-items:center;gap:8px;";
+    document.body.appendChild(banner);
+  }
+  banner.replaceChildren();
+  const text = document.createElement("span");
+  text.style.flex = "1";
+  text.textContent = status.message;
+  banner.appendChild(text);
+  if (status.repairable) {
+    const button = document.createElement("button");
+    button.textContent = status.repair_label;
+    button.onclick = () => jsonrpc_call("repair_manager");
+    banner.appendChild(button);
+  }
+}
+
+window.addEventListener("geph_manager_status", (e) =>
+  updateManagerBanner(e.detail)
+);
+jsonrpc_call("manager_status").then(updateManagerBanner);
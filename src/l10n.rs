@@ -0,0 +1,177 @@
+//! Localized strings for everything native: the tray, the bootstrap and update
+//! dialogs, the startup splash.
+//!
+//! The web frontend is translated from `gephgui/src/lib/l10n.csv`; native strings
+//! live in the same format in `src/l10n.csv`, and build.rs compiles both into one
+//! sorted table (ours overriding the frontend's on a shared key, so a native
+//! build never depends on the submodule's wording). Look strings up with `tr`:
+//! placeholders are a literal `{}`, filled in by the caller.
+//!
+//! The language is the one the user picked in the GUI, if any (persisted in
+//! prefs.rs and pushed by the frontend through `set_language`), otherwise the
+//! nearest match for the OS locale. Traditional Chinese (zh-TW/HK/MO, `Hant`) is
+//! its own column; an empty cell falls back to English, never to another
+//! language's text.
+
+use std::sync::Mutex;
+
+use crate::prefs;
+
+include!(concat!(env!("OUT_DIR"), "/l10n.rs"));
+
+/// The languages we ship, in catalog column order (see `LANGS` in build.rs).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lang {
+    En,
+    ZhCn,
+    ZhTw,
+    Fa,
+    Ar,
+    Ru,
+    Es,
+    Uk,
+}
+
+impl Lang {
+    /// The tag the frontend and the catalog use for this language.
+    pub fn code(self) -> &'static str {
+        match self {
+            Lang::En => "en",
+            Lang::ZhCn => "zh-CN",
+            Lang::ZhTw => "zh-TW",
+            Lang::Fa => "fa",
+            Lang::Ar => "ar",
+            Lang::Ru => "ru",
+            Lang::Es => "es",
+            Lang::Uk => "uk",
+        }
+    }
+
+    /// The language for a BCP 47-ish tag (`zh_TW`, `zh-Hant-HK`, `ru-RU`, …), if
+    /// we ship one.
+    pub fn from_tag(tag: &str) -> Option<Lang> {
+        let tag = tag.to_lowercase();
+        if tag.starts_with("zh") {
+            // Traditional Chinese for Taiwan/Hong Kong/Macau or an explicit
+            // `Hant` script subtag; Simplified otherwise.
+            return Some(
+                if ["tw", "hk", "mo", "hant"].iter().any(|t| tag.contains(t)) {
+                    Lang::ZhTw
+                } else {
+                    Lang::ZhCn
+                },
+            );
+        }
+        match tag.split(['-', '_', '.']).next().unwrap_or("") {
+            "en" => Some(Lang::En),
+            "fa" => Some(Lang::Fa),
+            "ar" => Some(Lang::Ar),
+            "ru" => Some(Lang::Ru),
+            "es" => Some(Lang::Es),
+            "uk" => Some(Lang::Uk),
+            _ => None,
+        }
+    }
+}
+
+/// The effective language, resolved once and then cached: reading prefs and the
+/// OS locale on every lookup would be wasteful, and the tray looks strings up on
+/// every event-loop wakeup.
+static CURRENT: Mutex<Option<Lang>> = Mutex::new(None);
+
+/// The language native strings are shown in right now.
+pub fn current() -> Lang {
+    *CURRENT.lock().unwrap().get_or_insert_with(|| {
+        prefs::get()
+            .language
+            .as_deref()
+            .and_then(Lang::from_tag)
+            .unwrap_or_else(detect)
+    })
+}
+
+/// The nearest language to the OS locale, falling back to English.
+pub fn detect() -> Lang {
+    sys_locale::get_locale()
+        .as_deref()
+        .and_then(Lang::from_tag)
+        .unwrap_or(Lang::En)
+}
+
+/// Persist the user's choice (`None` to follow the OS again) and switch to it.
+pub fn set_override(lang: Option<Lang>) -> anyhow::Result<()> {
+    prefs::update(|p| p.language = lang.map(|lang| lang.code().to_string()))?;
+    *CURRENT.lock().unwrap() = Some(lang.unwrap_or_else(detect));
+    Ok(())
+}
+
+/// `key` in the current language.
+pub fn tr(key: &'static str) -> &'static str {
+    tr_in(current(), key)
+}
+
+/// `key` in `lang`, or in English if it has no translation. An unknown key comes
+/// back as itself, so a typo shows up on screen rather than as a blank.
+pub fn tr_in(lang: Lang, key: &'static str) -> &'static str {
+    match CATALOG.binary_search_by(|(k, _)| k.cmp(&key)) {
+        Ok(i) => {
+            let texts = &CATALOG[i].1;
+            match texts[lang as usize] {
+                "" => texts[Lang::En as usize],
+                text => text,
+            }
+        }
+        Err(_) => {
+            tracing::warn!(key, "missing l10n key");
+            key
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags_pick_the_right_chinese() {
+        assert_eq!(Lang::from_tag("zh-CN"), Some(Lang::ZhCn));
+        assert_eq!(Lang::from_tag("zh_SG.UTF-8"), Some(Lang::ZhCn));
+        assert_eq!(Lang::from_tag("zh-TW"), Some(Lang::ZhTw));
+        assert_eq!(Lang::from_tag("zh-Hant-HK"), Some(Lang::ZhTw));
+        assert_eq!(Lang::from_tag("ru_RU.UTF-8"), Some(Lang::Ru));
+        assert_eq!(Lang::from_tag("de-DE"), None);
+    }
+
+    #[test]
+    fn codes_round_trip() {
+        for lang in [
+            Lang::En,
+            Lang::ZhCn,
+            Lang::ZhTw,
+            Lang::Fa,
+            Lang::Ar,
+            Lang::Ru,
+            Lang::Es,
+            Lang::Uk,
+        ] {
+            assert_eq!(Lang::from_tag(lang.code()), Some(lang));
+        }
+    }
+
+    #[test]
+    fn catalog_is_sorted_and_complete_in_english() {
+        assert!(CATALOG.windows(2).all(|w| w[0].0 < w[1].0));
+        assert!(CATALOG.iter().all(|(_, texts)| !texts[Lang::En as usize].is_empty()));
+    }
+
+    #[test]
+    fn traditional_chinese_is_not_simplified() {
+        assert_eq!(tr_in(Lang::ZhTw, "tray.show"), "顯示 Geph");
+        assert_eq!(tr_in(Lang::ZhCn, "tray.show"), "显示 Geph");
+    }
+
+    #[test]
+    fn unknown_keys_come_back_as_is() {
+        assert_eq!(tr_in(Lang::Fa, "no.such.key"), "no.such.key");
+    }
+}
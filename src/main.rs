@@ -17,14 +17,25 @@ use tao::platform::macos::{ActivationPolicy, EventLoopWindowTargetExtMacOS};
 #[cfg(target_os = "macos")]
 use tray_icon::menu::{Menu, PredefinedMenuItem, Submenu};
 
+mod autostart;
 mod autoupdate;
 #[cfg(any(target_os = "linux", target_os = "windows"))]
 mod bootstrap;
+#[cfg(any(target_os = "linux", target_os = "windows"))]
+mod diagnose;
+mod dialog;
+#[cfg(target_os = "linux")]
+mod elevate;
+mod l10n;
 mod manager;
 mod fakefs;
 
 mod mtbus;
+mod pause;
+mod prefs;
 mod rpc;
+#[cfg(any(target_os = "linux", target_os = "windows"))]
+mod splash;
 mod tray;
 
 use wry::{WebContext, WebView, WebViewBuilder};
@@ -40,6 +51,34 @@ fn main() -> anyhow::Result<()> {
         std::env::remove_var("HTTPS_PROXY");
     }
 
+    // `--dialog <json>`: we're the child showing a fallback prompt for a parent
+    // that can't use native dialogs (see dialog.rs).
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(spec) = args
+        .iter()
+        .position(|arg| arg == dialog::DIALOG_FLAG)
+        .and_then(|i| args.get(i + 1))
+    {
+        dialog::run_child(spec);
+    }
+
+    // Headless `--setup-manager [--yes]` for scripted deployments: installs and
+    // verifies the host manager, then exits with a status code. No window, no
+    // dialogs, and no single-instance lock, so it also works next to a running GUI.
+    #[cfg(any(target_os = "linux", target_os = "windows"))]
+    if std::env::args().any(|arg| arg == "--setup-manager") {
+        // We're a GUI-subsystem binary on Windows; borrow the caller's console so
+        // progress and errors are visible.
+        #[cfg(target_os = "windows")]
+        unsafe {
+            windows_sys::Win32::System::Console::AttachConsole(
+                windows_sys::Win32::System::Console::ATTACH_PARENT_PROCESS,
+            );
+        }
+        let yes = std::env::args().any(|arg| arg == "--yes");
+        std::process::exit(bootstrap::setup_manager_cli(yes));
+    }
+
     // The loopback HTTP port doubles as a single-instance lock: only one instance
     // can bind it. A second launch (e.g. the user opens Geph while an autostarted
     // `--hidden` instance is already running) fails to bind, so it pings the
@@ -60,19 +99,47 @@ fn main() -> anyhow::Result<()> {
     // The engine no longer runs in-process: a separate privileged `geph manager`
     // owns the tunnel, and we talk to it over its control protocol (see manager.rs).
 
-    // DO NOT run the autoupdate logic on flatpak, but otherwise it's good
-    if std::env::var("FLATPAK_ID").is_err() {
-        geph5_rt::block_on(autoupdate::prompt_cached_update_if_available())?;
-        geph5_rt::spawn(autoupdate::download_update_loop()).detach();
-    }
+    // There can only be one event loop, and the startup splash below needs it
+    // before the main window exists.
+    #[cfg_attr(not(any(target_os = "linux", target_os = "windows")), allow(unused_mut))]
+    let mut event_loop: EventLoop<Box<dyn FnOnce(&WebView, &Window) + Send + 'static>> =
+        EventLoopBuilder::with_user_event().build();
+
+    // Launched at login with `--hidden` (the installer's autostart shortcut, or the
+    // entry autostart.rs writes): come up as just the tray icon, no window. Manual
+    // launches show the window.
+    let start_hidden = std::env::args().any(|arg| arg == "--hidden");
 
-    // Make sure the privileged host manager is installed, current, and answering
-    // before we bring up the webview that talks to it. May show a native dialog and
-    // elevate via pkexec (Linux) or UAC (Windows), or ask for a relaunch; returns
-    // false if we should exit now.
+    let autoupdate = autoupdate::enabled();
+
+    // Before bringing up the webview: offer any downloaded update, then make sure
+    // the privileged host manager is installed, current, and answering. Either may
+    // show a native dialog; the bootstrap may elevate (pkexec/run0/sudo on Linux,
+    // UAC on Windows) or ask for a relaunch, returning false if we should exit now.
+    // This runs on a worker thread behind a progress window (splash.rs), so a long
+    // elevation or service start doesn't look like a hang.
     #[cfg(any(target_os = "linux", target_os = "windows"))]
-    if !bootstrap::ensure_manager() {
-        return Ok(());
+    {
+        let startup = std::thread::spawn(move || -> anyhow::Result<bool> {
+            if autoupdate {
+                splash::set_step(splash::Step::CheckingUpdates);
+                geph5_rt::block_on(autoupdate::prompt_cached_update_if_available())?;
+            }
+            Ok(bootstrap::ensure_manager())
+        });
+        match splash::run_until(&mut event_loop, startup, !start_hidden) {
+            Some(Ok(true)) => {}
+            Some(Ok(false)) | None => return Ok(()),
+            Some(Err(err)) => return Err(err),
+        }
+    }
+    // macOS has no bootstrap, and its update dialog must stay on the main thread.
+    #[cfg(not(any(target_os = "linux", target_os = "windows")))]
+    if autoupdate {
+        geph5_rt::block_on(autoupdate::prompt_cached_update_if_available())?;
+    }
+    if autoupdate {
+        geph5_rt::spawn(autoupdate::download_update_loop()).detach();
     }
 
     // Start a simple HTTP server in a separate thread
@@ -81,10 +148,8 @@ fn main() -> anyhow::Result<()> {
             let url = request.url().trim_start_matches('/');
             // Single-instance "show yourself" ping from a second launch.
             if url == "__show" {
-                mt_enqueue(|_, window| {
-                    window.set_visible(true);
-                    window.set_focus();
-                });
+                // Minimized rather than hidden when there's no tray.
+                mt_enqueue(|_, window| tray::show_window(window));
                 request
                     .respond(tiny_http::Response::from_string("ok"))
                     .ok();
@@ -119,8 +184,6 @@ fn main() -> anyhow::Result<()> {
         }
     });
 
-    let event_loop: EventLoop<Box<dyn FnOnce(&WebView, &Window) + Send + 'static>> =
-        EventLoopBuilder::with_user_event().build();
     let evt_proxy = event_loop.create_proxy();
     std::thread::spawn(move || {
         loop {
@@ -129,10 +192,6 @@ fn main() -> anyhow::Result<()> {
         }
     });
 
-    // Launched at login via the installer's autostart shortcut with `--hidden`:
-    // come up as just the tray icon, no window. Manual launches show the window.
-    let start_hidden = std::env::args().any(|arg| arg == "--hidden");
-
     let window = WindowBuilder::new()
         .with_resizable(true)
         .with_visible(!start_hidden)
@@ -237,6 +296,11 @@ fn main() -> anyhow::Result<()> {
                     Ok(t) => tray = Some(t),
                     Err(err) => eprintln!("failed to build tray icon: {err:#}"),
                 }
+                // A `--hidden` start with no visible tray would leave nothing on
+                // screen at all; come up minimized instead.
+                if !window.is_visible() && !tray::tray_available() {
+                    tray::hide_window(&window);
+                }
             }
             Event::UserEvent(e) => e(&webview, &window),
             Event::WindowEvent {
@@ -244,17 +308,10 @@ fn main() -> anyhow::Result<()> {
                 ..
             } => {
                 // The `geph manager` is a persistent, privileged process that owns
-                // the tunnel and keeps managing it in the background.
-                //
-                // We must never leave the manager active with no tray icon, so while
-                // it's connecting/connected we only hide to tray; we exit (taking the
-                // tray with us) only once it's disconnected. The tray's "Quit" item
-                // disconnects first, then exits, preserving the same invariant.
-                if tray::tunnel_active() {
-                    println!("tunnel active; hiding GUI to tray instead of exiting");
-                    window.set_visible(false);
-                } else {
-                    println!("tunnel down; closing the GUI");
+                // the tunnel and keeps managing it in the background, so what the
+                // close button does depends on the tunnel state and the user's
+                // preference; see `tray::on_close_requested`.
+                if tray::on_close_requested(&window) {
                     *control_flow = ControlFlow::Exit;
                 }
             }
@@ -149,6 +149,37 @@ pub async fn manager_reachable() -> bool {
         .is_some_and(|r| matches!(r, Ok(Ok(_))))
 }
 
+/// Whether the answering manager speaks a different revision of the control
+/// protocol than the `geph5-misc-rpc` we're built against, as after a partial
+/// upgrade. The protocol has no version to ask for, so this reads it off the
+/// calls the GUI depends on: a method the manager doesn't know, or an answer we
+/// can't decode, means the two sides have drifted apart. `None` if it didn't
+/// answer at all, which is `manager_reachable`'s business.
+pub async fn protocol_mismatch() -> Option<bool> {
+    let settings = client().get_settings().timeout(Duration::from_secs(2)).await?;
+    let status = client().status().timeout(Duration::from_secs(2)).await?;
+    Some(drifted(&settings) || drifted(&status))
+}
+
+/// Did a `GephCtl` call fail because the two sides disagree on the protocol,
+/// rather than over the transport or in the manager's own logic?
+fn drifted<T, E>(result: &Result<Result<T, String>, GephCtlError<E>>) -> bool {
+    matches!(
+        result,
+        Err(GephCtlError::NotFound | GephCtlError::FailedDecode(_))
+    )
+}
+
+/// The manager's state as seen by the once-a-second GUI poll: `None` if it
+/// didn't answer, otherwise whether the tunnel is wanted up (see
+/// `manager_connected`). Short timeout, like `manager_reachable`.
+pub async fn manager_state() -> Option<bool> {
+    match client().get_settings().timeout(Duration::from_secs(2)).await {
+        Some(Ok(Ok(settings))) => Some(settings.connected),
+        _ => None,
+    }
+}
+
 /// Whether the user currently wants the tunnel up (mirrors the old "is the
 /// manager process running" semantics, which only existed while connected).
 /// Short timeout: the tray polls this once a second.
@@ -159,6 +190,30 @@ pub async fn manager_connected() -> bool {
     }
 }
 
+/// The tunnel's local HTTP proxy, if the tunnel is wanted up and has one, so the
+/// GUI's own requests (update checks, see autoupdate/source.rs) can get out where
+/// direct connections are blocked. A wildcard listen address is dialed on
+/// loopback.
+pub async fn tunnel_http_proxy() -> Option<std::net::SocketAddr> {
+    let view = client()
+        .get_settings()
+        .timeout(Duration::from_secs(2))
+        .await?
+        .ok()?
+        .ok()?;
+    if !view.connected {
+        return None;
+    }
+    // `ProxySettings` is the frontend's `ProxyArgs` shape (see rpc.rs); only the
+    // one field matters here.
+    let proxy = serde_json::to_value(view.tunnel_settings().proxy?).ok()?;
+    let mut addr: std::net::SocketAddr = proxy.get("http_proxy_listen")?.as_str()?.parse().ok()?;
+    if addr.ip().is_unspecified() {
+        addr.set_ip(std::net::Ipv4Addr::LOCALHOST.into());
+    }
+    Some(addr)
+}
+
 /// Forward a raw engine RPC (`conn_info`, `broker_rpc`, `net_status`,
 /// `stat_history`, `recent_logs`, `start_registration`, …) to the manager, which
 /// relays it to its always-running child geph5-client. This is what makes
@@ -0,0 +1,99 @@
+//! Timed "pause protection" (tray: Pause for 5 / 15 / 60 minutes).
+//!
+//! A pause is just a `stop_daemon` now plus a scheduled `reconnect` later. The
+//! timer is a detached background task, so it keeps running while the window is
+//! hidden. Each pause gets a generation number; the timer only reconnects if its
+//! generation is still the current pause when it fires, so resuming early,
+//! reconnecting by hand, or starting a new pause all cancel the pending resume.
+
+use std::{
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use crate::manager;
+
+struct Pause {
+    generation: u64,
+    until: Instant,
+    /// Set once `stop_daemon` has gone through. Before that, the state poll can
+    /// still see the tunnel up and must not mistake it for a manual reconnect.
+    stopped: bool,
+}
+
+static PAUSE: Mutex<Option<Pause>> = Mutex::new(None);
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Disconnect now and reconnect automatically after `duration`.
+pub fn pause_for(duration: Duration) {
+    let generation = GENERATION.fetch_add(1, Ordering::Relaxed) + 1;
+    *PAUSE.lock().unwrap() = Some(Pause {
+        generation,
+        until: Instant::now() + duration,
+        stopped: false,
+    });
+    geph5_rt::spawn(async move {
+        if let Err(err) = manager::stop_daemon().await {
+            eprintln!("failed to pause protection: {err:#}");
+            take_if_current(generation);
+            return;
+        }
+        if let Some(pause) = PAUSE.lock().unwrap().as_mut()
+            && pause.generation == generation
+        {
+            pause.stopped = true;
+        }
+        tokio::time::sleep(duration).await;
+        if take_if_current(generation) {
+            let _ = manager::reconnect().await;
+        }
+    })
+    .detach();
+}
+
+/// End the current pause early and reconnect.
+pub fn resume_now() {
+    if PAUSE.lock().unwrap().take().is_some() {
+        geph5_rt::spawn(async {
+            let _ = manager::reconnect().await;
+        })
+        .detach();
+    }
+}
+
+/// Drop any pending resume without reconnecting. Called whenever the user takes
+/// the connection state into their own hands (manual connect, quit).
+pub fn cancel() {
+    PAUSE.lock().unwrap().take();
+}
+
+/// Time left until the pending resume, if a pause is in effect.
+pub fn remaining() -> Option<Duration> {
+    PAUSE
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|pause| pause.until.saturating_duration_since(Instant::now()))
+}
+
+/// Fed the polled tunnel state: if the tunnel comes back up while we're paused
+/// (reconnected from the window, the CLI, …), the pause is over.
+pub fn observe_active(active: bool) {
+    let mut pause = PAUSE.lock().unwrap();
+    if active && pause.as_ref().is_some_and(|p| p.stopped) {
+        *pause = None;
+    }
+}
+
+fn take_if_current(generation: u64) -> bool {
+    let mut pause = PAUSE.lock().unwrap();
+    if pause.as_ref().is_some_and(|p| p.generation == generation) {
+        *pause = None;
+        true
+    } else {
+        false
+    }
+}
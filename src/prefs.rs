@@ -0,0 +1,104 @@
+//! Persisted GUI-side preferences.
+//!
+//! Tunnel settings (exit, VPN mode, …) belong to the manager, which persists them
+//! itself; this is only for how the *GUI* behaves. Stored as JSON under the
+//! config dir, read once and cached, and rewritten whole on every change. A
+//! missing or unreadable file just means defaults.
+
+use std::{
+    path::PathBuf,
+    sync::{LazyLock, Mutex},
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Prefs {
+    pub close_action: CloseAction,
+    /// Language tag chosen in the GUI for native strings (see l10n.rs); `None`
+    /// follows the OS locale.
+    pub language: Option<String>,
+    /// Which releases auto-update follows (see autoupdate/channel.rs).
+    pub update_channel: UpdateChannel,
+    /// Keep the installer of the version an update replaced, for rollback (see
+    /// autoupdate/cache.rs).
+    pub keep_previous_installer: bool,
+}
+
+/// What the window's close button does.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CloseAction {
+    /// Hide to tray while the tunnel is active, quit otherwise.
+    #[default]
+    Auto,
+    HideToTray,
+    /// Quit; if the tunnel is active, confirm first and offer to disconnect.
+    Quit,
+    Ask,
+}
+
+/// Which releases auto-update follows. Ordered from most to least stable.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UpdateChannel {
+    #[default]
+    Stable,
+    Beta,
+    Nightly,
+}
+
+impl UpdateChannel {
+    /// The name used in manifest tracks (`linux-beta`) and by the frontend.
+    pub fn name(self) -> &'static str {
+        match self {
+            UpdateChannel::Stable => "stable",
+            UpdateChannel::Beta => "beta",
+            UpdateChannel::Nightly => "nightly",
+        }
+    }
+}
+
+static PREFS: LazyLock<Mutex<Prefs>> = LazyLock::new(|| Mutex::new(load().unwrap_or_default()));
+
+fn path() -> Option<PathBuf> {
+    Some(dirs::config_dir()?.join("geph5-gui").join("prefs.json"))
+}
+
+fn load() -> Option<Prefs> {
+    let bytes = std::fs::read(path()?).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+/// A snapshot of the current preferences.
+pub fn get() -> Prefs {
+    PREFS.lock().unwrap().clone()
+}
+
+/// Modify the preferences and persist the result.
+pub fn update(f: impl FnOnce(&mut Prefs)) -> anyhow::Result<()> {
+    let mut prefs = PREFS.lock().unwrap();
+    f(&mut prefs);
+    let path = path().context("no config dir")?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(&path, serde_json::to_vec_pretty(&*prefs)?)
+        .with_context(|| format!("write {}", path.display()))?;
+    Ok(())
+}
+
+/// Forget all preferences, deleting the file.
+pub fn reset() -> anyhow::Result<()> {
+    let mut prefs = PREFS.lock().unwrap();
+    *prefs = Prefs::default();
+    let path = path().context("no config dir")?;
+    match std::fs::remove_file(&path) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+            Err(err).with_context(|| format!("remove {}", path.display()))
+        }
+        _ => Ok(()),
+    }
+}
@@ -7,11 +7,16 @@ use tao::dpi::LogicalSize;
 use webbrowser::open_browser;
 
 use crate::{
-    WINDOW_HEIGHT, WINDOW_WIDTH,
+    WINDOW_HEIGHT, WINDOW_WIDTH, autostart,
+    autoupdate::{self, DownloadProgress, InstallWhen, UpdateCacheInfo, UpdateStatus},
+    l10n::{self, Lang, tr},
     manager::{
         daemon_rpc, manager_connected, restart_daemon, set_exit_constraint, start_daemon, stop_daemon,
     },
     mtbus::mt_enqueue,
+    pause,
+    prefs::{self, CloseAction, UpdateChannel},
+    tray,
 };
 
 #[derive(Deserialize)]
@@ -39,6 +44,19 @@ pub fn ipc_handle(ipc_string: String) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Push an unsolicited event to the webview, dispatched as a DOM `CustomEvent`
+/// on `window` with `detail` as its payload. For native state changes the
+/// frontend would otherwise have to poll for.
+pub fn push_event(name: &'static str, detail: impl Serialize) {
+    let detail = serde_json::to_string(&detail).unwrap();
+    mt_enqueue(move |wv, _| {
+        let _ = wv.evaluate_script(&format!(
+            "window.dispatchEvent(new CustomEvent({}, {{ detail: {detail} }}))",
+            json!(name)
+        ));
+    });
+}
+
 /// The derived RPC trait. Add in all the methods your JS side expects.
 #[nanorpc_derive]
 #[async_trait]
@@ -55,6 +73,8 @@ trait IpcProtocol {
 
     /// Start the tunnel (via the manager) with the given arguments.
     async fn start_daemon(&self, args: DaemonArgs) -> Result<(), String> {
+        // Connecting by hand ends any tray "pause protection" countdown.
+        pause::cancel();
         start_daemon(args).await.map_err(|s| format!("{:?}", s))
     }
 
@@ -217,7 +237,14 @@ trait IpcProtocol {
         let daemon_logs: Vec<String> = serde_json::from_value(daemon_logs).unwrap_or_default();
         let daemon_logs = daemon_logs.join("\n");
 
-        format!("===== DAEMON =====\n\n {daemon_logs}")
+        #[cfg(any(target_os = "linux", target_os = "windows"))]
+        let diagnostics = geph5_rt::spawn_blocking(crate::diagnose::report).await;
+        #[cfg(not(any(target_os = "linux", target_os = "windows")))]
+        let diagnostics = String::from("(not available on this platform)");
+
+        format!(
+            "===== DAEMON =====\n\n {daemon_logs}\n\n===== MANAGER DIAGNOSTICS =====\n\n{diagnostics}"
+        )
     }
 
     /// Get the icon of an app, returning it as a URL string.
@@ -257,8 +284,160 @@ trait IpcProtocol {
 
     /// Whether this platform supports auto-updates.
     async fn supports_autoupdate(&self) -> bool {
-        // Replace with real implementation
-        todo!()
+        autoupdate::enabled()
+    }
+
+    /// Where the update lifecycle stands: idle, checking, downloading (with a
+    /// percentage), ready (with version and release notes), or failed. Changes are
+    /// also pushed as `geph_update_status` events.
+    async fn update_status(&self) -> UpdateStatus {
+        autoupdate::update_status()
+    }
+
+    /// Check for an update now. Returns immediately; the outcome shows up as
+    /// `geph_update_status` events.
+    async fn check_for_updates(&self) -> Result<(), String> {
+        autoupdate::check_now().map_err(|e| format!("{:?}", e))
+    }
+
+    /// Install the downloaded update: stops the tunnel and quits for the
+    /// installer, so this only ever returns an error.
+    async fn install_update(&self) -> Result<(), String> {
+        autoupdate::install_now().await.map_err(|e| format!("{:?}", e))
+    }
+
+    /// Install the downloaded update later instead, without dropping the
+    /// tunnel: `disconnected` (the next time it goes down) or `quit`. The
+    /// status becomes `deferred` until then.
+    async fn defer_update_install(&self, when: InstallWhen) -> Result<(), String> {
+        autoupdate::defer_install(when).map_err(|e| format!("{:?}", e))
+    }
+
+    /// Which releases auto-update follows: `stable`, `beta` or `nightly`.
+    async fn get_update_channel(&self) -> UpdateChannel {
+        prefs::get().update_channel
+    }
+
+    /// Persist the update channel and check it right away. Switching from a beta
+    /// or nightly build back to stable offers stable's release as a downgrade.
+    async fn set_update_channel(&self, channel: UpdateChannel) -> Result<(), String> {
+        autoupdate::set_channel(channel).map_err(|e| format!("{:?}", e))
+    }
+
+    /// Disk used by downloaded updates, and whether the previous installer is
+    /// kept for rollback.
+    async fn update_cache_info(&self) -> Result<UpdateCacheInfo, String> {
+        autoupdate::cache_info().map_err(|e| format!("{:?}", e))
+    }
+
+    /// Keep (or stop keeping) the previous version's installer for rollback.
+    async fn set_keep_previous_installer(&self, keep: bool) -> Result<(), String> {
+        autoupdate::set_keep_previous(keep).map_err(|e| format!("{:?}", e))
+    }
+
+    /// Delete all downloaded updates, including a pending one.
+    async fn clear_update_cache(&self) -> Result<(), String> {
+        autoupdate::clear_downloads().map_err(|e| format!("{:?}", e))
+    }
+
+    /// Put the downloaded update off for a day.
+    async fn remind_update_later(&self) -> Result<(), String> {
+        autoupdate::remind_later().map_err(|e| format!("{:?}", e))
+    }
+
+    /// Never offer the downloaded update's version again.
+    async fn skip_update_version(&self) -> Result<(), String> {
+        autoupdate::skip_version().map_err(|e| format!("{:?}", e))
+    }
+
+    /// The update download in progress, if any. Changes are also pushed as
+    /// `geph_update_progress` events (`null` once it ends).
+    async fn update_download_progress(&self) -> Option<DownloadProgress> {
+        autoupdate::download_progress()
+    }
+
+    /// Whether this platform supports managing launch-at-login from the app.
+    async fn supports_autostart(&self) -> bool {
+        autostart::SUPPORTED
+    }
+
+    /// Whether Geph launches (hidden, to the tray) at login.
+    async fn get_autostart(&self) -> Result<bool, String> {
+        autostart::is_enabled().map_err(|e| format!("{:?}", e))
+    }
+
+    /// Turn launch-at-login on or off.
+    async fn set_autostart(&self, enabled: bool) -> Result<(), String> {
+        autostart::set_enabled(enabled).map_err(|e| format!("{:?}", e))
+    }
+
+    /// Whether the background service answers, with the localized strings for the
+    /// window's "service unavailable" banner. Changes are also pushed as
+    /// `geph_manager_status` events.
+    async fn manager_status(&self) -> ManagerStatus {
+        ManagerStatus::current()
+    }
+
+    /// Start repairing the background service (explain → elevate → wait). Returns
+    /// immediately; the outcome shows up as a `geph_manager_status` event.
+    async fn repair_manager(&self) {
+        tray::spawn_repair();
+    }
+
+    /// Remove the background service (confirm → disconnect → elevate → verify),
+    /// optionally erasing the GUI's data too, then quit. Returns immediately; the
+    /// whole flow runs in native dialogs.
+    async fn uninstall_manager(&self) -> Result<(), String> {
+        #[cfg(any(target_os = "linux", target_os = "windows"))]
+        {
+            std::thread::spawn(crate::bootstrap::uninstall_manager);
+            Ok(())
+        }
+        #[cfg(not(any(target_os = "linux", target_os = "windows")))]
+        {
+            Err("uninstalling the background service is not supported on this platform".into())
+        }
+    }
+
+    /// What the window's close button does.
+    async fn get_close_action(&self) -> CloseAction {
+        prefs::get().close_action
+    }
+
+    /// Persist what the window's close button does.
+    async fn set_close_action(&self, action: CloseAction) -> Result<(), String> {
+        prefs::update(|p| p.close_action = action).map_err(|e| format!("{:?}", e))
+    }
+
+    /// The language tag native strings (tray, dialogs) are shown in, and whether
+    /// it was picked by the user rather than taken from the OS.
+    async fn get_language(&self) -> LanguageInfo {
+        LanguageInfo {
+            language: l10n::current().code().to_string(),
+            overridden: prefs::get().language.is_some(),
+        }
+    }
+
+    /// Show native strings in the language with this tag (one the frontend
+    /// offers, e.g. `zh-TW`), persisted across restarts; `None` follows the OS
+    /// locale again. The tray relabels itself right away.
+    async fn set_language(&self, language: Option<String>) -> Result<(), String> {
+        let lang = match language {
+            Some(tag) => {
+                Some(Lang::from_tag(&tag).ok_or_else(|| format!("unsupported language {tag:?}"))?)
+            }
+            None => None,
+        };
+        l10n::set_override(lang).map_err(|e| format!("{:?}", e))?;
+        // The unavailable-service banner text comes from us.
+        push_event("geph_manager_status", ManagerStatus::current());
+        Ok(())
+    }
+
+    /// Quit the GUI but leave the tunnel running, after a native warning.
+    async fn quit_keep_tunnel(&self) {
+        // The warning is a modal dialog, which belongs on the event-loop thread.
+        mt_enqueue(|_, _| tray::quit_keeping_tunnel());
     }
 
     /// Obtain native info for debugging.
@@ -373,3 +552,29 @@ pub struct NativeInfo {
     pub platform_details: String,
     pub version: String,
 }
+
+#[derive(Debug, Serialize)]
+pub struct LanguageInfo {
+    pub language: String,
+    pub overridden: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ManagerStatus {
+    pub reachable: bool,
+    /// Whether this platform can repair the service from the GUI.
+    pub repairable: bool,
+    pub message: String,
+    pub repair_label: String,
+}
+
+impl ManagerStatus {
+    pub fn current() -> Self {
+        Self {
+            reachable: tray::manager_reachable(),
+            repairable: cfg!(any(target_os = "linux", target_os = "windows")),
+            message: tr("tray.service_unavailable").to_string(),
+            repair_label: tr("tray.repair").to_string(),
+        }
+    }
+}
@@ -0,0 +1,209 @@
+//! Startup progress window (Linux + Windows).
+//!
+//! Before the main window exists, startup can block for a long time: the cached
+//! update prompt, then the manager bootstrap, which may wait on an elevation
+//! prompt, run the installer, and poll up to ~10s for the service to answer. With
+//! nothing on screen, users assume the app hung and launch it again (or kill it).
+//! So that work runs on a worker thread while the main thread pumps the (single)
+//! event loop via `run_return` and shows a small splash: the step in progress, how
+//! long it has been running, a "taking longer than usual" hint once it drags on,
+//! and a Cancel button, which quits.
+//!
+//! Quitting isn't safe at every step, though: mid-install it would leave the
+//! service half set up, and the update prompt may itself be running the installer.
+//! During those steps the button is disabled, and a cancel from closing the window
+//! is held until the step is over.
+//!
+//! Steps are reported with `set_step` from wherever the work happens (see
+//! bootstrap.rs); the splash polls them. It only appears once startup has taken
+//! longer than `SHOW_AFTER`, so the common already-installed case never flashes a
+//! window, and never for `--hidden` autostarts.
+
+use std::{
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use serde_json::json;
+use tao::{
+    dpi::LogicalSize,
+    event::{Event, WindowEvent},
+    event_loop::{ControlFlow, EventLoop},
+    platform::run_return::EventLoopExtRunReturn,
+    window::{Window, WindowBuilder},
+};
+use wry::{WebContext, WebView, WebViewBuilder};
+
+use crate::l10n::tr;
+
+/// How long startup may take before the splash appears.
+const SHOW_AFTER: Duration = Duration::from_millis(500);
+/// How long one step may take before we add a reassuring hint.
+const SLOW_AFTER: Duration = Duration::from_secs(15);
+
+/// What startup is busy with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Step {
+    CheckingUpdates,
+    CheckingService,
+    WaitingForElevation,
+    Installing,
+    StartingService,
+}
+
+impl Step {
+    fn label(self) -> &'static str {
+        tr(match self {
+            Step::CheckingUpdates => "splash.checking_updates",
+            Step::CheckingService => "splash.checking_service",
+            Step::WaitingForElevation => "splash.waiting_for_elevation",
+            Step::Installing => "splash.installing",
+            Step::StartingService => "splash.starting_service",
+        })
+    }
+
+    /// Whether quitting now would leave nothing half done.
+    fn cancellable(self) -> bool {
+        !matches!(
+            self,
+            Step::CheckingUpdates | Step::WaitingForElevation | Step::Installing
+        )
+    }
+}
+
+static STEP: Mutex<Option<(Step, Instant)>> = Mutex::new(None);
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+
+/// Report the step startup has moved on to.
+pub fn set_step(step: Step) {
+    *STEP.lock().unwrap() = Some((step, Instant::now()));
+}
+
+/// Run the event loop, showing the splash if `job` takes a while (and `show` is
+/// set), until `job` finishes. Returns its result, or `None` if the user cancelled.
+pub fn run_until<T, E>(event_loop: &mut EventLoop<E>, job: JoinHandle<T>, show: bool) -> Option<T> {
+    let start = Instant::now();
+    let mut splash: Option<(Window, WebView)> = None;
+    let mut tried = false;
+    let mut web_context = WebContext::new(dirs::cache_dir().map(|dir| dir.join("geph5-splash")));
+
+    event_loop.run_return(|event, target, control_flow| {
+        *control_flow = ControlFlow::WaitUntil(Instant::now() + Duration::from_millis(250));
+        match event {
+            Event::WindowEvent {
+                event: WindowEvent::CloseRequested,
+                ..
+            } => CANCELLED.store(true, Ordering::Relaxed),
+            Event::MainEventsCleared => {
+                if job.is_finished() || (CANCELLED.load(Ordering::Relaxed) && cancellable()) {
+                    *control_flow = ControlFlow::Exit;
+                    return;
+                }
+                if !tried && show && start.elapsed() >= SHOW_AFTER {
+                    tried = true;
+                    // Never fatal: worst case we're back to no feedback at all.
+                    match build(target, &mut web_context) {
+                        Ok(built) => splash = Some(built),
+                        Err(err) => tracing::warn!(err = debug(err), "could not show the splash"),
+                    }
+                }
+                if let Some((_, webview)) = &splash {
+                    let _ = webview.evaluate_script(&format!("update({})", status()));
+                }
+            }
+            _ => {}
+        }
+    });
+    drop(splash);
+
+    if CANCELLED.load(Ordering::Relaxed) {
+        return None;
+    }
+    match job.join() {
+        Ok(result) => Some(result),
+        Err(panic) => std::panic::resume_unwind(panic),
+    }
+}
+
+/// Whether the current step may be cancelled.
+fn cancellable() -> bool {
+    STEP.lock()
+        .unwrap()
+        .is_none_or(|(step, _)| step.cancellable())
+}
+
+/// The current step, as the splash page's `update()` expects it.
+fn status() -> serde_json::Value {
+    let (label, elapsed) = match *STEP.lock().unwrap() {
+        Some((step, since)) => (step.label(), since.elapsed()),
+        None => (Step::CheckingService.label(), Duration::ZERO),
+    };
+    let hint = if elapsed >= SLOW_AFTER {
+        tr("splash.slow_hint")
+    } else {
+        ""
+    };
+    json!({
+        "step": label,
+        "elapsed": format!("{}s", elapsed.as_secs()),
+        "hint": hint,
+        "cancellable": cancellable(),
+    })
+}
+
+fn build<E>(
+    target: &tao::event_loop::EventLoopWindowTarget<E>,
+    web_context: &mut WebContext,
+) -> anyhow::Result<(Window, WebView)> {
+    let window = WindowBuilder::new()
+        .with_title("Geph")
+        .with_resizable(false)
+        .with_inner_size(LogicalSize::new(360, 150))
+        .build(target)?;
+    let html = PAGE.replace("{cancel}", tr("common.cancel"));
+    let builder = WebViewBuilder::with_web_context(web_context)
+        .with_html(html)
+        .with_ipc_handler(|req| {
+            if req.body() == "cancel" {
+                CANCELLED.store(true, Ordering::Relaxed);
+            }
+        });
+
+    #[cfg(target_os = "windows")]
+    let webview = builder.build(&window)?;
+    #[cfg(target_os = "linux")]
+    let webview = {
+        use tao::platform::unix::WindowExtUnix;
+        use wry::WebViewBuilderExtUnix;
+        let vbox = window
+            .default_vbox()
+            .ok_or_else(|| anyhow::anyhow!("window has no GTK vbox"))?;
+        builder.build_gtk(vbox)?
+    };
+    Ok((window, webview))
+}
+
+const PAGE: &str = r#"<!doctype html>
+<html><head><meta charset="utf-8"><style>
+  body { font: 14px system-ui, sans-serif; margin: 20px; color: #222; user-select: none; }
+  #row { display: flex; justify-content: space-between; align-items: baseline; }
+  #elapsed { color: #888; font-variant-numeric: tabular-nums; }
+  #hint { color: #666; font-size: 12px; margin-top: 8px; min-height: 2.5em; }
+  button { float: right; }
+</style></head><body>
+  <div id="row"><span id="step"></span><span id="elapsed"></span></div>
+  <div id="hint"></div>
+  <button id="cancel" onclick="window.ipc.postMessage('cancel')">{cancel}</button>
+  <script>
+    function update(s) {
+      document.getElementById("step").textContent = s.step;
+      document.getElementById("elapsed").textContent = s.elapsed;
+      document.getElementById("hint").textContent = s.hint;
+      document.getElementById("cancel").disabled = !s.cancellable;
+    }
+  </script>
+</body></html>"#;
@@ -5,17 +5,41 @@
 //! we keep a tray icon alive for the whole process lifetime and only let the
 //! process exit while the manager is disconnected:
 //!
-//!   * closing the window while the manager is active hides to tray (see the
-//!     `CloseRequested` handler in main.rs),
+//!   * closing the window while the manager is active hides to tray, asks, or
+//!     confirms a disconnect, per the user's `CloseAction` preference (see
+//!     `on_close_requested`),
 //!   * the tray "Quit" disconnects first, then exits,
 //!   * the auto-update path already disconnects before exiting.
 //!
+//! An update the user accepted for later (autoupdate/deferred.rs) shows in the
+//! tooltip, with an "Install update now" item. The poll below hands it the
+//! tunnel state so it can install once disconnected, and quitting by any path
+//! but "Quit, keep connected" installs it on the way out.
+//!
+//! "Hides to tray" assumes there *is* a visible tray. Building the icon can fail,
+//! and on Linux it can succeed with nothing to show it (stock GNOME has no
+//! StatusNotifierItem host without the AppIndicator extension). Hiding the window
+//! then would leave exactly the invisible-GUI-with-active-tunnel situation above,
+//! so `tray_available` checks for a real tray and, without one, we minimize
+//! instead. Relaunching Geph also surfaces the window via the `__show` ping.
+//!
+//! The one deliberate exception is "Quit, keep connected", for users who rely on
+//! the manager's persistence. It warns first that the tunnel will be left running
+//! with no tray, and that opening Geph again brings the tray back.
+//!
 //! `manager_connected()` (the persisted `connected` flag) is `true` exactly while
 //! the manager is connecting or connected, so it is our "active" signal. We mirror
 //! it into an atomic once a second because the close handler is synchronous and
 //! must not block on an RPC.
+//!
+//! The same poll notices the manager going away while we're open (service
+//! killed, uninstalled, socket gone). After a few missed polls we flag it in the
+//! tray tooltip, enable a "Repair background service" item, and push a
+//! `geph_manager_status` event to the webview; Repair re-runs the startup
+//! bootstrap's explain → elevate → wait flow in place.
 
 use std::{
+    cell::{Cell, RefCell},
     sync::atomic::{AtomicBool, Ordering},
     time::Duration,
 };
@@ -23,12 +47,35 @@ use std::{
 use tao::window::Window;
 use tray_icon::{
     Icon, MouseButton, MouseButtonState, TrayIcon, TrayIconBuilder, TrayIconEvent,
-    menu::{Menu, MenuEvent, MenuItem, PredefinedMenuItem},
+    menu::{Menu, MenuEvent, MenuItem, PredefinedMenuItem, Submenu},
 };
 
-use crate::manager;
+use crate::{
+    autoupdate::{self, InstallWhen},
+    dialog::{self, Level},
+    l10n::{self, Lang, tr},
+    manager, pause,
+    prefs::{self, CloseAction},
+    rpc::{self, ManagerStatus},
+};
 
 static TUNNEL_ACTIVE: AtomicBool = AtomicBool::new(false);
+static TRAY_BUILT: AtomicBool = AtomicBool::new(false);
+static MANAGER_REACHABLE: AtomicBool = AtomicBool::new(true);
+static REPAIRING: AtomicBool = AtomicBool::new(false);
+/// Linux: whether a tray host was on the session bus when last asked (assumed
+/// until first asked).
+#[cfg(target_os = "linux")]
+static TRAY_HOST: AtomicBool = AtomicBool::new(true);
+
+/// Consecutive unanswered polls before we call the manager lost, so a manager
+/// restart (upgrade, reconnect) doesn't flash the "unavailable" state.
+const LOST_AFTER_MISSES: u32 = 3;
+
+/// Linux: polls between tray-host checks. Each one spawns `gdbus`, so not every
+/// second; a tray extension turned on or off shows up within this many seconds.
+#[cfg(target_os = "linux")]
+const TRAY_HOST_EVERY: u32 = 5;
 
 /// Last-polled tunnel-active (connecting/connected) state. Read synchronously by
 /// the close handler to decide hide-to-tray vs. exit.
@@ -36,29 +83,155 @@ pub fn tunnel_active() -> bool {
     TUNNEL_ACTIVE.load(Ordering::Relaxed)
 }
 
-/// Background task mirroring `manager::manager_connected()` into `TUNNEL_ACTIVE` ~1s.
+/// Last-polled manager reachability (with `LOST_AFTER_MISSES` hysteresis).
+pub fn manager_reachable() -> bool {
+    MANAGER_REACHABLE.load(Ordering::Relaxed)
+}
+
+/// Background task mirroring `manager::manager_state()` into `TUNNEL_ACTIVE` and
+/// `MANAGER_REACHABLE` ~1s, announcing reachability changes to the webview. On
+/// Linux it also refreshes `TRAY_HOST` every `TRAY_HOST_EVERY` polls, starting
+/// with the first, off the UI thread; until that answers a tray is assumed.
 pub fn spawn_state_poll() {
     geph5_rt::spawn(async {
+        let mut misses = 0;
+        #[cfg(target_os = "linux")]
+        let mut polls = 0u32;
         loop {
-            TUNNEL_ACTIVE.store(manager::manager_connected().await, Ordering::Relaxed);
+            #[cfg(target_os = "linux")]
+            {
+                if polls % TRAY_HOST_EVERY == 0 {
+                    let present = geph5_rt::spawn_blocking(status_notifier_host_present).await;
+                    // A hidden window was counting on the tray (a `--hidden` start
+                    // decides before the first answer); bring it back minimized.
+                    if TRAY_HOST.swap(present, Ordering::Relaxed) && !present {
+                        crate::mtbus::mt_enqueue(|_, window| {
+                            if !window.is_visible() {
+                                hide_window(window);
+                            }
+                        });
+                    }
+                }
+                polls = polls.wrapping_add(1);
+            }
+            let state = manager::manager_state().await;
+            let active = state.unwrap_or(false);
+            TUNNEL_ACTIVE.store(active, Ordering::Relaxed);
+            pause::observe_active(active);
+            autoupdate::observe_tunnel(state);
+
+            misses = if state.is_some() { 0 } else { misses + 1 };
+            let reachable = misses < LOST_AFTER_MISSES;
+            if MANAGER_REACHABLE.swap(reachable, Ordering::Relaxed) != reachable {
+                eprintln!("geph manager reachable: {reachable}");
+                rpc::push_event("geph_manager_status", ManagerStatus::current());
+            }
             tokio::time::sleep(Duration::from_secs(1)).await;
         }
     })
     .detach();
 }
 
+/// Re-run the manager install/repair flow on a worker thread (it blocks on native
+/// dialogs and the elevation prompt). At most one repair runs at a time.
+pub fn spawn_repair() {
+    if REPAIRING.swap(true, Ordering::Relaxed) {
+        return;
+    }
+    std::thread::spawn(|| {
+        #[cfg(any(target_os = "linux", target_os = "windows"))]
+        let repaired = crate::bootstrap::repair_manager();
+        #[cfg(not(any(target_os = "linux", target_os = "windows")))]
+        let repaired = false;
+        eprintln!("manager repair finished; reachable again: {repaired}");
+        REPAIRING.store(false, Ordering::Relaxed);
+    });
+}
+
+/// Whether the user can actually see (and click) our tray icon right now.
+pub fn tray_available() -> bool {
+    if !TRAY_BUILT.load(Ordering::Relaxed) {
+        return false;
+    }
+    #[cfg(target_os = "linux")]
+    {
+        TRAY_HOST.load(Ordering::Relaxed)
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        true
+    }
+}
+
+/// Linux: is a StatusNotifierWatcher (the tray host's registry, which our
+/// AppIndicator icon registers with) on the session bus? Asked via `gdbus`, which
+/// ships with GLib and therefore with both every GTK desktop and the Flatpak
+/// runtime. If we can't ask at all, assume a tray rather than change behavior.
+/// Blocks for up to a second on a stuck bus, so it's only run from the state
+/// poll; the UI thread reads the cached `TRAY_HOST`.
+#[cfg(target_os = "linux")]
+fn status_notifier_host_present() -> bool {
+    let out = std::process::Command::new("gdbus")
+        .args([
+            "call",
+            "--session",
+            "--timeout",
+            "1",
+            "--dest",
+            "org.freedesktop.DBus",
+            "--object-path",
+            "/org/freedesktop/DBus",
+            "--method",
+            "org.freedesktop.DBus.NameHasOwner",
+            "org.kde.StatusNotifierWatcher",
+        ])
+        .output();
+    match out {
+        Ok(out) if out.status.success() => String::from_utf8_lossy(&out.stdout).contains("true"),
+        _ => true,
+    }
+}
+
+/// Get the window out of the way: hide it to the tray if there is one, otherwise
+/// minimize it so it stays reachable from the taskbar/dock.
+pub fn hide_window(window: &Window) {
+    if tray_available() {
+        window.set_visible(false);
+    } else {
+        println!("no visible system tray; minimizing instead of hiding");
+        if !window.is_visible() {
+            window.set_visible(true);
+        }
+        window.set_minimized(true);
+    }
+}
+
 /// Owns the live tray icon (dropping it removes the icon, so it must outlive the
 /// event loop) plus the menu items we toggle/identify on click.
 pub struct Tray {
-    _tray: TrayIcon,
+    icon: TrayIcon,
     show: MenuItem,
     /// A single Connect/Disconnect item whose label tracks the manager state, so
     /// the menu shows only the relevant action instead of both with one greyed out.
     toggle: MenuItem,
+    /// "Pause protection" submenu, enabled only while the tunnel is up.
+    pause: Submenu,
+    /// (item, duration, label key) for each entry of the `pause` submenu.
+    pause_items: Vec<(MenuItem, Duration, &'static str)>,
+    /// Ends a pause early; enabled only while one is pending.
+    resume: MenuItem,
+    /// "Repair background service"; enabled only while the manager is lost.
+    repair: MenuItem,
+    /// "Install update now"; enabled only while an update waits for a safe moment.
+    update: MenuItem,
+    /// "Quit, keep connected"; enabled only while the tunnel is active.
+    quit_keep: MenuItem,
     quit: MenuItem,
-    /// Localized labels for the two `toggle` states.
-    connect_label: &'static str,
-    disconnect_label: &'static str,
+    /// The language the fixed labels are in; when the user picks another in the
+    /// GUI, `pump_tray_events` relabels the menu in place.
+    lang: Cell<Lang>,
+    /// The tooltip last pushed to the icon, so we only touch it on change.
+    tooltip: RefCell<String>,
 }
 
 /// Build the tray icon and its context menu. Must be called on the main thread
@@ -66,19 +239,46 @@ pub struct Tray {
 /// message hook, the macOS `NSStatusItem`, the Linux `gtk`/AppIndicator widget)
 /// must be created and serviced on the thread that runs the event loop.
 pub fn build_tray() -> anyhow::Result<Tray> {
-    let labels = l10n::labels(l10n::detect());
-    let show = MenuItem::new(labels.show, true, None);
+    let lang = l10n::current();
+    let show = MenuItem::new(tr("tray.show"), true, None);
     // One Connect/Disconnect toggle; `pump_tray_events` keeps its label in sync
     // with the manager state. Starts as "Connect" (disconnected) and is corrected
     // on the first poll.
-    let toggle = MenuItem::new(labels.connect, true, None);
-    let quit = MenuItem::new(labels.quit, true, None);
+    let toggle = MenuItem::new(tr("tray.connect"), true, None);
+    let pause_items: Vec<_> = [
+        ("tray.pause_5", 5 * 60),
+        ("tray.pause_15", 15 * 60),
+        ("tray.pause_60", 60 * 60),
+    ]
+    .into_iter()
+    .map(|(key, secs)| {
+        (
+            MenuItem::new(tr(key), true, None),
+            Duration::from_secs(secs),
+            key,
+        )
+    })
+    .collect();
+    let pause = Submenu::new(tr("tray.pause"), false);
+    for (item, _, _) in &pause_items {
+        pause.append(item)?;
+    }
+    let resume = MenuItem::new(tr("tray.resume"), false, None);
+    let repair = MenuItem::new(tr("tray.repair"), false, None);
+    let update = MenuItem::new(tr("tray.install_update"), false, None);
+    let quit_keep = MenuItem::new(tr("tray.quit_keep"), false, None);
+    let quit = MenuItem::new(tr("common.quit"), true, None);
 
     let menu = Menu::new();
     menu.append(&show)?;
     menu.append(&PredefinedMenuItem::separator())?;
     menu.append(&toggle)?;
+    menu.append(&pause)?;
+    menu.append(&resume)?;
+    menu.append(&repair)?;
+    menu.append(&update)?;
     menu.append(&PredefinedMenuItem::separator())?;
+    menu.append(&quit_keep)?;
     menu.append(&quit)?;
 
     #[allow(unused_mut)]
@@ -102,14 +302,21 @@ pub fn build_tray() -> anyhow::Result<Tray> {
     }
 
     let tray = builder.build()?;
+    TRAY_BUILT.store(true, Ordering::Relaxed);
 
     Ok(Tray {
-        _tray: tray,
+        icon: tray,
         show,
         toggle,
+        pause,
+        pause_items,
+        resume,
+        repair,
+        update,
+        quit_keep,
         quit,
-        connect_label: labels.connect,
-        disconnect_label: labels.disconnect,
+        lang: Cell::new(lang),
+        tooltip: RefCell::new("Geph".into()),
     })
 }
 
@@ -117,17 +324,60 @@ pub fn build_tray() -> anyhow::Result<Tray> {
 /// `MainEventsCleared` arm: tray-icon posts its window messages to this same
 /// thread's queue, so every click wakes the loop and lands here.
 pub fn pump_tray_events(tray: &Tray, window: &Window) {
+    let lang = l10n::current();
+    if tray.lang.replace(lang) != lang {
+        relabel(tray);
+    }
     let active = tunnel_active();
     // Show exactly one of Connect / Disconnect, matching the manager state.
-    let desired_label = if active {
-        tray.disconnect_label
+    let desired_label = tr(if active {
+        "tray.disconnect"
     } else {
-        tray.connect_label
-    };
+        "tray.connect"
+    });
     if tray.toggle.text().as_str() != desired_label {
         tray.toggle.set_text(desired_label);
     }
 
+    // Pausing only makes sense while connected; resuming only while paused. The
+    // tooltip carries the countdown so a hover shows when protection comes back.
+    let remaining = pause::remaining();
+    let can_pause = active && remaining.is_none();
+    if tray.pause.is_enabled() != can_pause {
+        tray.pause.set_enabled(can_pause);
+    }
+    if tray.resume.is_enabled() != remaining.is_some() {
+        tray.resume.set_enabled(remaining.is_some());
+    }
+    if tray.quit_keep.is_enabled() != active {
+        tray.quit_keep.set_enabled(active);
+    }
+    let reachable = manager_reachable();
+    if tray.repair.is_enabled() == reachable {
+        tray.repair.set_enabled(!reachable);
+    }
+    let deferred = autoupdate::deferred_update();
+    if tray.update.is_enabled() != deferred.is_some() {
+        tray.update.set_enabled(deferred.is_some());
+    }
+    let tooltip = match (remaining, deferred) {
+        _ if !reachable => tr("tray.service_unavailable").to_string(),
+        (Some(left), _) => {
+            let secs = left.as_secs();
+            tr("tray.paused_tooltip").replace("{}", &format!("{}:{:02}", secs / 60, secs % 60))
+        }
+        (None, Some(deferral)) => tr(match deferral.when {
+            InstallWhen::Disconnected => "tray.update_on_disconnect_tooltip",
+            InstallWhen::Quit => "tray.update_on_quit_tooltip",
+        })
+        .replace("{}", &deferral.version),
+        (None, None) => "Geph".to_string(),
+    };
+    if *tray.tooltip.borrow() != tooltip {
+        let _ = tray.icon.set_tooltip(Some(&tooltip));
+        *tray.tooltip.borrow_mut() = tooltip;
+    }
+
     // Coalesce every "show the window" request in this drain into a single
     // `show_window` at the end. A fast double-click on the tray delivers two
     // `Click{Up}` events (plus a `DoubleClick`) in one drain; calling
@@ -150,19 +400,34 @@ pub fn pump_tray_events(tray: &Tray, window: &Window) {
                 })
                 .detach();
             } else {
+                // A manual connect supersedes any pending timed resume.
+                pause::cancel();
                 geph5_rt::spawn(async {
                     let _ = manager::reconnect().await;
                 })
                 .detach();
             }
-        } else if event.id == *tray.quit.id() {
-            // Honor the invariant: disconnect first, then exit, so the manager is
-            // never left active with no tray.
+        } else if let Some((_, duration, _)) = tray
+            .pause_items
+            .iter()
+            .find(|(item, _, _)| event.id == *item.id())
+        {
+            pause::pause_for(*duration);
+        } else if event.id == *tray.resume.id() {
+            pause::resume_now();
+        } else if event.id == *tray.repair.id() {
+            spawn_repair();
+        } else if event.id == *tray.update.id() {
             geph5_rt::spawn(async {
-                let _ = manager::stop_daemon().await;
-                std::process::exit(0);
+                if let Err(err) = autoupdate::install_now().await {
+                    eprintln!("failed to install the update: {err:#}");
+                }
             })
             .detach();
+        } else if event.id == *tray.quit_keep.id() {
+            quit_keeping_tunnel();
+        } else if event.id == *tray.quit.id() {
+            quit_disconnecting();
         }
     }
 
@@ -182,121 +447,139 @@ pub fn pump_tray_events(tray: &Tray, window: &Window) {
     }
 }
 
-/// Bring the window to the foreground. Guarded so each native call is a no-op when
-/// already in the desired state: this both cuts the message churn that feeds the
-/// re-entrancy panic (see `pump_tray_events`) and keeps the `__show` single-instance
-/// path cheap when the window is already up.
-fn show_window(window: &Window) {
-    if window.is_minimized() {
-        window.set_minimized(false);
-    }
-    if !window.is_visible() {
-        window.set_visible(true);
-    }
-    if !window.is_focused() {
-        window.set_focus();
+/// Re-set every fixed menu label after a language change. The toggle and the
+/// tooltip are recomputed on each pump anyway.
+fn relabel(tray: &Tray) {
+    tray.show.set_text(tr("tray.show"));
+    tray.pause.set_text(tr("tray.pause"));
+    for (item, _, key) in &tray.pause_items {
+        item.set_text(tr(*key));
     }
+    tray.resume.set_text(tr("tray.resume"));
+    tray.repair.set_text(tr("tray.repair"));
+    tray.update.set_text(tr("tray.install_update"));
+    tray.quit_keep.set_text(tr("tray.quit_keep"));
+    tray.quit.set_text(tr("common.quit"));
+}
+
+/// Honor the invariant: disconnect first, then exit, so the manager is never left
+/// active with no tray. A pending timed resume dies with us; a deferred update is
+/// installed, which disconnects and exits by itself.
+pub fn quit_disconnecting() {
+    pause::cancel();
+    geph5_rt::spawn(async {
+        autoupdate::install_on_quit().await;
+        let _ = manager::stop_daemon().await;
+        std::process::exit(0);
+    })
+    .detach();
 }
 
-/// Tray-menu localization, covering the same languages the web frontend supports
-/// (gephgui/src/lib/l10n.ts): en, zh-CN, zh-TW, fa, ar, ru, es, uk. The
-/// Connect/Disconnect wording matches the frontend's own `l10n.csv`. The tray is
-/// a native element built once at startup, so — like the frontend's
-/// `detectNearestBrowserLocale` — we pick the nearest language from the OS locale
-/// via `sys-locale`, falling back to English.
-mod l10n {
-    #[derive(Clone, Copy)]
-    pub enum Lang {
-        En,
-        ZhCn,
-        ZhTw,
-        Fa,
-        Ar,
-        Ru,
-        Es,
-        Uk,
+/// Exit while leaving the tunnel up, after a warning the user must confirm. Runs
+/// a modal dialog, so call it on the event-loop thread. A deferred update stays
+/// uninstalled, since installing would drop the tunnel; it's offered again at the
+/// next start.
+pub fn quit_keeping_tunnel() {
+    if dialog::confirm(
+        Level::Warning,
+        tr("close.keep_title"),
+        tr("close.keep_body"),
+        tr("tray.quit_keep"),
+        tr("common.cancel"),
+    ) {
+        println!("quitting the GUI, leaving the tunnel up as requested");
+        std::process::exit(0);
     }
+}
 
-    pub fn detect() -> Lang {
-        let locale = sys_locale::get_locale().unwrap_or_default().to_lowercase();
-        if locale.starts_with("zh") {
-            // Traditional Chinese for Taiwan/Hong Kong/Macau or an explicit
-            // `Hant` script subtag; Simplified otherwise.
-            if ["tw", "hk", "mo", "hant"].iter().any(|t| locale.contains(t)) {
-                Lang::ZhTw
-            } else {
-                Lang::ZhCn
+/// Handle the window's close button according to the persisted `CloseAction`.
+/// Returns `true` if the event loop should exit right away; the disconnecting
+/// paths exit on their own once the disconnect has gone through.
+pub fn on_close_requested(window: &Window) -> bool {
+    let active = tunnel_active();
+    match (prefs::get().close_action, active) {
+        // Hide while connecting/connected, exit once disconnected. This is the
+        // long-standing default and needs no prompt.
+        (CloseAction::Auto, true) => {
+            println!("tunnel active; hiding GUI to tray instead of exiting");
+            hide_window(window);
+            false
+        }
+        (CloseAction::Auto | CloseAction::Quit, false) => {
+            println!("tunnel down; closing the GUI");
+            quit_now()
+        }
+        (CloseAction::HideToTray, _) => {
+            hide_window(window);
+            false
+        }
+        (CloseAction::Quit, true) => {
+            match choose(
+                tr("close.title"),
+                tr("close.connected_body"),
+                [
+                    tr("tray.disconnect_quit"),
+                    tr("tray.quit_keep"),
+                    tr("common.cancel"),
+                ],
+            ) {
+                0 => quit_disconnecting(),
+                1 => quit_keeping_tunnel(),
+                _ => {}
             }
-        } else {
-            match locale.split(['-', '_']).next().unwrap_or("") {
-                "fa" => Lang::Fa,
-                "ar" => Lang::Ar,
-                "ru" => Lang::Ru,
-                "es" => Lang::Es,
-                "uk" => Lang::Uk,
-                _ => Lang::En,
+            false
+        }
+        (CloseAction::Ask, _) => {
+            let quit = tr(if active {
+                "tray.disconnect_quit"
+            } else {
+                "common.quit"
+            });
+            match choose(
+                tr("close.title"),
+                tr("close.ask_body"),
+                [tr("tray.hide_to_tray"), quit, tr("common.cancel")],
+            ) {
+                0 => hide_window(window),
+                1 if active => quit_disconnecting(),
+                1 => return quit_now(),
+                _ => {}
             }
+            false
         }
     }
+}
 
-    pub struct Labels {
-        pub show: &'static str,
-        pub connect: &'static str,
-        pub disconnect: &'static str,
-        pub quit: &'static str,
+/// Quitting with the tunnel already down: exit right away (`true`), unless a
+/// deferred update needs installing first, which `quit_disconnecting` does.
+fn quit_now() -> bool {
+    if autoupdate::deferred_update().is_some() {
+        quit_disconnecting();
+        false
+    } else {
+        true
     }
+}
 
-    pub fn labels(lang: Lang) -> Labels {
-        match lang {
-            Lang::En => Labels {
-                show: "Show Geph",
-                connect: "Connect",
-                disconnect: "Disconnect",
-                quit: "Quit",
-            },
-            Lang::ZhCn => Labels {
-                show: "显示 Geph",
-                connect: "连接",
-                disconnect: "断开",
-                quit: "退出",
-            },
-            Lang::ZhTw => Labels {
-                show: "顯示 Geph",
-                connect: "連接",
-                disconnect: "斷開",
-                quit: "結束",
-            },
-            Lang::Fa => Labels {
-                show: "نمایش Geph",
-                connect: "اتصال",
-                disconnect: "قطع اتصال",
-                quit: "خروج",
-            },
-            Lang::Ar => Labels {
-                show: "إظهار Geph",
-                connect: "اتصال",
-                disconnect: "قطع الاتصال",
-                quit: "خروج",
-            },
-            Lang::Ru => Labels {
-                show: "Показать Geph",
-                connect: "Подключить",
-                disconnect: "Отключить",
-                quit: "Выход",
-            },
-            Lang::Es => Labels {
-                show: "Mostrar Geph",
-                connect: "Conectar",
-                disconnect: "Desconectar",
-                quit: "Salir",
-            },
-            Lang::Uk => Labels {
-                show: "Показати Geph",
-                connect: "Підключити",
-                disconnect: "Відключити",
-                quit: "Вийти",
-            },
-        }
+/// Three-button prompt (see dialog.rs). Returns the index of the chosen button; closing
+/// the dialog counts as the last one (cancel).
+fn choose(title: &str, body: &str, buttons: [&str; 3]) -> usize {
+    dialog::show(Level::Info, title, body, &buttons).unwrap_or(2)
+}
+
+/// Bring the window to the foreground. Guarded so each native call is a no-op when
+/// already in the desired state: this both cuts the message churn that feeds the
+/// re-entrancy panic (see `pump_tray_events`) and keeps the `__show` single-instance
+/// path cheap when the window is already up.
+pub fn show_window(window: &Window) {
+    if window.is_minimized() {
+        window.set_minimized(false);
+    }
+    if !window.is_visible() {
+        window.set_visible(true);
+    }
+    if !window.is_focused() {
+        window.set_focus();
     }
 }
 